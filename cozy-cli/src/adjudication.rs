@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Args;
+use cozy_matches::adjudication::*;
+use cozy_matches::tablebase::{Tablebase, TablebaseAdjudicator};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdjudicationArgs {
+    /// Adjudicate a loss once both engines agree a side is behind by this many centipawns.
+    #[clap(long)]
+    pub resign_score: Option<i32>,
+    /// Consecutive moves each engine has to agree for before resigning.
+    #[clap(long, default_value_t = 3)]
+    pub resign_moves: u32,
+    /// Adjudicate a draw once both engines' scores stay within this many centipawns of zero.
+    #[clap(long)]
+    pub draw_score: Option<i32>,
+    /// Consecutive move pairs the scores have to stay within the draw score for.
+    #[clap(long, default_value_t = 8)]
+    pub draw_moves: u32,
+    /// Full move number before which no draw is adjudicated.
+    #[clap(long, default_value_t = 40)]
+    pub draw_min_move: u32,
+    /// Restart the draw count after captures and pawn moves.
+    #[clap(long)]
+    pub draw_reset_on_irreversible: bool,
+    /// Stop the game after this many plies.
+    #[clap(long)]
+    pub max_plies: Option<u32>,
+    /// Instead of drawing at the ply limit, award the game to a side whose last score is this many centipawns ahead.
+    #[clap(long)]
+    pub max_plies_score: Option<i32>,
+    /// Directory of Syzygy tablebase files to adjudicate endgames with. May be repeated.
+    #[clap(long)]
+    pub syzygy_path: Vec<PathBuf>
+}
+
+impl AdjudicationArgs {
+    pub fn adjudicators(&self) -> std::io::Result<Vec<Box<dyn Adjudicator>>> {
+        let mut adjudicators: Vec<Box<dyn Adjudicator>> = Vec::new();
+        if let Some(score) = self.resign_score {
+            adjudicators.push(Box::new(ResignAdjudicator::new(ResignAdjudication {
+                score,
+                move_count: self.resign_moves
+            })));
+        }
+        if let Some(score) = self.draw_score {
+            adjudicators.push(Box::new(DrawAdjudicator::new(DrawAdjudication {
+                min_move: self.draw_min_move,
+                score,
+                move_count: self.draw_moves,
+                reset_on_irreversible: self.draw_reset_on_irreversible
+            })));
+        }
+        if !self.syzygy_path.is_empty() {
+            let tablebase = Tablebase::open(&self.syzygy_path)?;
+            adjudicators.push(Box::new(TablebaseAdjudicator::new(Arc::new(tablebase))));
+        }
+        if let Some(max_plies) = self.max_plies {
+            adjudicators.push(Box::new(MaxMovesAdjudicator::new(MaxMovesAdjudication {
+                max_plies,
+                score: self.max_plies_score
+            })));
+        }
+        Ok(adjudicators)
+    }
+}
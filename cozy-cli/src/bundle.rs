@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cozy_matches::engine::{Engine, UciOptionField, Transcript, TranscriptDirection};
+use cozy_matches::engine_match::GameTermination;
+use serde::Serialize;
+
+use crate::EngineConfig;
+
+/// Everything needed to reproduce a failed search outside of a match.
+#[derive(Debug, Serialize)]
+struct ReproBundle<'a> {
+    engine: &'a str,
+    config: &'a EngineConfig,
+    termination: String,
+    options: BTreeMap<&'a str, String>,
+    /// The search fields are `None` if the engine failed before it was
+    /// asked to search in this game, e.g. while starting the game.
+    fen: Option<String>,
+    position: Option<&'a str>,
+    go: Option<&'a str>,
+    clocks: Option<String>,
+    stderr: Vec<String>
+}
+
+fn option_value(field: &UciOptionField) -> String {
+    match field {
+        UciOptionField::Check { value } => value.to_string(),
+        UciOptionField::Spin { value, .. } => value.to_string(),
+        UciOptionField::Combo { value, labels } => labels[*value].clone(),
+        UciOptionField::String { value } => value.clone()
+    }
+}
+
+/// Builds a transcript that `cozy-cli replay` can run directly.
+fn replay_commands(name: &str, engine: &Engine, termination: &GameTermination) -> Vec<String> {
+    let transcript = Transcript::in_memory(name.to_owned());
+    let sent = |line: &str| transcript.log(TranscriptDirection::Sent, line);
+    let received = |line: &str| transcript.log(TranscriptDirection::Received, line);
+
+    sent("uci");
+    received("uciok");
+    for (option, field) in engine.options() {
+        let value = option_value(field);
+        if !value.is_empty() {
+            sent(&format!("setoption name {} value {}", option, value));
+        }
+    }
+    sent("isready");
+    received("readyok");
+    if let Some(search) = engine.last_search() {
+        sent(&search.position);
+        sent(&search.go);
+        // A bare bestmove waits for the search without comparing the result.
+        match termination {
+            GameTermination::IllegalMove { mv } => received(&format!("bestmove {}", mv)),
+            _ => received("bestmove")
+        }
+    }
+    transcript.lines().unwrap()
+}
+
+/// Writes `bundle.json` and a replayable `commands.log` to a new directory
+/// under `dir`, returning its path.
+pub fn write(dir: &Path, name: &str, config: &EngineConfig, engine: &Engine, termination: &GameTermination) -> std::io::Result<PathBuf> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let path = dir.join(format!("{}-{}", millis, name));
+    std::fs::create_dir_all(&path)?;
+
+    let search = engine.last_search();
+    let bundle = ReproBundle {
+        engine: name,
+        config,
+        termination: format!("{:?}", termination),
+        options: engine.options().iter()
+            .map(|(option, field)| (option.as_str(), option_value(field)))
+            .collect(),
+        fen: search.map(|s| s.board.to_string()),
+        position: search.map(|s| s.position.trim_end()),
+        go: search.map(|s| s.go.trim_end()),
+        clocks: search.and_then(|s| s.limit.time_limit).map(|t| format!("{:?}", t)),
+        stderr: engine.recent_stderr()
+    };
+    std::fs::write(path.join("bundle.json"), serde_json::to_string_pretty(&bundle)?)?;
+
+    let mut commands = replay_commands(name, engine, termination).join("\n");
+    commands.push('\n');
+    std::fs::write(path.join("commands.log"), commands)?;
+    Ok(path)
+}
+
+pub fn write_and_report(dir: &Path, name: &str, config: &EngineConfig, engine: &Engine, termination: &GameTermination) {
+    match write(dir, name, config, engine, termination) {
+        Ok(path) => {
+            println!("wrote reproducer bundle to {}", path.display());
+            println!("    cozy-cli replay --engine {} {}", name, path.join("commands.log").display());
+        }
+        Err(e) => eprintln!("failed to write reproducer bundle: {}", e)
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::Write;
+use std::time::Duration;
+
+use cozy_chess::*;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+
+use cozy_matches::engine::{canonicalize_move, AnalysisLimit, AnalysisTimeLimit, EngineAnalysis, EngineAnalysisError, EngineAnalysisEvent};
+use cozy_matches::game::ChessGame;
+use cozy_matches::player::{legal_moves, Player, PlayerFuture};
+
+const HELP: &str = "enter a move in SAN (Nf3) or UCI (g1f3), `undo` to take back your last move or `resign`";
+
+/// A player that reads moves from stdin.
+#[derive(Debug)]
+pub struct HumanPlayer {
+    color: Color,
+    input: Lines<BufReader<Stdin>>
+}
+
+impl HumanPlayer {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            input: BufReader::new(tokio::io::stdin()).lines()
+        }
+    }
+}
+
+impl Player for HumanPlayer {
+    fn new_game(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn analyze(&mut self, game: &ChessGame, limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError> {
+        let board = game.board().clone();
+        let stack = game.stack();
+        let last_move = stack.last().map(|&(mv, _)| {
+            let before = stack.len().checked_sub(2).map_or(game.init_pos(), |i| &stack[i].1);
+            san(before, mv)
+        });
+        let can_take_back = stack.len() >= 2;
+        print_board(&board, self.color);
+        if let Some(mv) = last_move {
+            println!("{} played {}", !self.color, mv);
+        }
+        if let Some(AnalysisTimeLimit::TimeLeft { white_time, black_time, .. }) = limit.time_limit {
+            let clock = |time: Option<Duration>| time.map_or("-".to_owned(), |t| format!("{:.1}s", t.as_secs_f64()));
+            println!("white {}, black {}", clock(white_time), clock(black_time));
+        }
+        Ok(EngineAnalysis::new(async_stream::try_stream! {
+            loop {
+                print!("> ");
+                std::io::stdout().flush()?;
+                let line = match self.input.next_line().await? {
+                    Some(line) => line,
+                    None => {
+                        yield EngineAnalysisEvent::Resign;
+                        break;
+                    }
+                };
+                match line.trim() {
+                    "" => {}
+                    "help" => println!("{}", HELP),
+                    "resign" => {
+                        yield EngineAnalysisEvent::Resign;
+                        break;
+                    }
+                    "undo" | "takeback" if can_take_back => {
+                        yield EngineAnalysisEvent::Takeback;
+                        break;
+                    }
+                    "undo" | "takeback" => println!("nothing to take back"),
+                    input => match parse_move(&board, input) {
+                        Some(mv) => {
+                            yield EngineAnalysisEvent::BestMove(mv);
+                            break;
+                        }
+                        None => println!("illegal move `{}`; {}", input, HELP)
+                    }
+                }
+            }
+        }))
+    }
+
+    fn stop(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn print_board(board: &Board, perspective: Color) {
+    let mut ranks = Rank::ALL;
+    if perspective == Color::White {
+        ranks.reverse();
+    }
+    let mut files = File::ALL;
+    if perspective == Color::Black {
+        files.reverse();
+    }
+    for rank in ranks {
+        let row: Vec<String> = files.iter()
+            .map(|&file| {
+                let square = Square::new(file, rank);
+                match (board.piece_on(square), board.color_on(square)) {
+                    (Some(piece), Some(Color::White)) => piece.to_string().to_uppercase(),
+                    (Some(piece), _) => piece.to_string(),
+                    _ => ".".to_owned()
+                }
+            })
+            .collect();
+        println!("{} {}", rank, row.join(" "));
+    }
+    let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+    println!("  {}", files.join(" "));
+}
+
+/// Formats a legal move in standard algebraic notation.
+fn san(board: &Board, mv: Move) -> String {
+    let piece = board.piece_on(mv.from).unwrap();
+    let mut san = if board.color_on(mv.to) == board.color_on(mv.from) {
+        if mv.to.file() > mv.from.file() { "O-O" } else { "O-O-O" }.to_owned()
+    } else {
+        let capture = board.piece_on(mv.to).is_some()
+            || (piece == Piece::Pawn && mv.from.file() != mv.to.file());
+        let mut san = String::new();
+        if piece == Piece::Pawn {
+            if capture {
+                san += &mv.from.file().to_string();
+            }
+        } else {
+            san += &piece.to_string().to_uppercase();
+            let others: Vec<Square> = legal_moves(board).into_iter()
+                .filter(|other| other.to == mv.to && other.from != mv.from)
+                .filter(|other| board.piece_on(other.from) == Some(piece))
+                .map(|other| other.from)
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|sq| sq.file() != mv.from.file()) {
+                    san += &mv.from.file().to_string();
+                } else if others.iter().all(|sq| sq.rank() != mv.from.rank()) {
+                    san += &mv.from.rank().to_string();
+                } else {
+                    san += &mv.from.to_string();
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san += &mv.to.to_string();
+        if let Some(promotion) = mv.promotion {
+            san += &format!("={}", promotion.to_string().to_uppercase());
+        }
+        san
+    };
+    let mut child = board.clone();
+    child.play_unchecked(mv);
+    if child.status() == GameStatus::Won {
+        san.push('#');
+    } else if !child.checkers().is_empty() {
+        san.push('+');
+    }
+    san
+}
+
+/// Parses a move in SAN or UCI notation, returning `None` if it isn't legal.
+fn parse_move(board: &Board, input: &str) -> Option<Move> {
+    let normalize = |s: &str| s.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+    let input_san = normalize(input);
+    let by_san = legal_moves(board).into_iter()
+        .find(|&mv| normalize(&san(board, mv)) == input_san);
+    if by_san.is_some() {
+        return by_san;
+    }
+    let mv = canonicalize_move(board, input.parse().ok()?, false);
+    board.is_legal(mv).then_some(mv)
+}
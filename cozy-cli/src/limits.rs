@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use clap::Args;
+use cozy_matches::engine::AnalysisSearchLimit;
+use cozy_matches::engine_match::{EngineMatchTimeConfig, ChessClockState};
+use cozy_matches::time_control::{TimeControl, parse_duration};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+fn clap_parse_time_control(s: &str) -> Result<TimeControl, String> {
+    s.parse().map_err(|e| format!("{}", e))
+}
+
+fn clap_parse_duration(s: &str) -> Result<Duration, String> {
+    parse_duration(s).ok_or_else(|| "invalid duration".to_owned())
+}
+
+/// Durations are stored in the same format they're given on the command line in.
+fn serialize_duration<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.collect_str(&format_args!("{}s", duration.as_secs_f64())),
+        None => serializer.serialize_none()
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| parse_duration(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid duration {:?}", s))))
+        .transpose()
+}
+
+/// How long an engine may search each move. Unset fields fall back to
+/// less specific settings, see [`EngineLimits::or`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineLimits {
+    /// Time control, such as `10+0.1` or `40/5m`.
+    #[clap(long, value_parser = clap_parse_time_control)]
+    #[serde(alias = "time_control")]
+    pub tc: Option<TimeControl>,
+    /// Charge the time control for the nodes searched, at this many nodes per second.
+    #[clap(long)]
+    pub nps: Option<u64>,
+    /// Node limit per move.
+    #[clap(long)]
+    pub nodes: Option<u64>,
+    /// Depth limit per move.
+    #[clap(long)]
+    pub depth: Option<u32>,
+    /// Fixed time per move, such as `100ms`.
+    #[clap(long, value_parser = clap_parse_duration)]
+    #[serde(serialize_with = "serialize_duration", deserialize_with = "deserialize_duration")]
+    pub movetime: Option<Duration>,
+    /// Search without a time limit, until the node or depth limit is reached.
+    #[clap(long)]
+    pub infinite: bool
+}
+
+impl EngineLimits {
+    /// Fills in the settings missing from `self` from `fallback`. A time
+    /// limit set in `self` replaces all of `fallback`'s time limits.
+    pub fn or(&self, fallback: &EngineLimits) -> EngineLimits {
+        let has_time_limit = self.tc.is_some() || self.movetime.is_some() || self.infinite;
+        EngineLimits {
+            tc: self.tc.clone().or_else(|| fallback.tc.clone().filter(|_| !has_time_limit)),
+            nps: self.nps.or(fallback.nps),
+            nodes: self.nodes.or(fallback.nodes),
+            depth: self.depth.or(fallback.depth),
+            movetime: self.movetime.or(fallback.movetime.filter(|_| !has_time_limit)),
+            infinite: self.infinite || (fallback.infinite && !has_time_limit)
+        }
+    }
+
+    /// Returns `None` if no limit is set at all.
+    pub fn time_config(&self) -> Option<EngineMatchTimeConfig> {
+        let search_limit = (self.nodes.is_some() || self.depth.is_some()).then_some(AnalysisSearchLimit {
+            nodes: self.nodes,
+            depth: self.depth
+        });
+        let clock = if let Some(time_control) = self.tc.clone() {
+            match self.nps {
+                Some(nps) => ChessClockState::NodesAsTime { time_control, nps },
+                None => ChessClockState::Clock(time_control)
+            }
+        } else if let Some(movetime) = self.movetime {
+            ChessClockState::MoveTime(movetime)
+        } else if self.infinite || search_limit.is_some() {
+            ChessClockState::Infinite
+        } else {
+            return None;
+        };
+        Some(EngineMatchTimeConfig { search_limit, clock })
+    }
+}
+
+/// Limits for a single side. These take precedence over the engine config,
+/// which in turn takes precedence over the limits given for both sides.
+#[derive(Debug, Clone, Args)]
+pub struct SideLimitArgs {
+    /// Time control for white, such as `10+0.1` or `40/5m`.
+    #[clap(long, value_parser = clap_parse_time_control)]
+    pub white_tc: Option<TimeControl>,
+    /// Charge white's time control for the nodes searched, at this many nodes per second.
+    #[clap(long)]
+    pub white_nps: Option<u64>,
+    /// Node limit per move for white.
+    #[clap(long)]
+    pub white_nodes: Option<u64>,
+    /// Depth limit per move for white.
+    #[clap(long)]
+    pub white_depth: Option<u32>,
+    /// Fixed time per move for white, such as `100ms`.
+    #[clap(long, value_parser = clap_parse_duration)]
+    pub white_movetime: Option<Duration>,
+    /// Let white search without a time limit.
+    #[clap(long)]
+    pub white_infinite: bool,
+    /// Time control for black, such as `10+0.1` or `40/5m`.
+    #[clap(long, value_parser = clap_parse_time_control)]
+    pub black_tc: Option<TimeControl>,
+    /// Charge black's time control for the nodes searched, at this many nodes per second.
+    #[clap(long)]
+    pub black_nps: Option<u64>,
+    /// Node limit per move for black.
+    #[clap(long)]
+    pub black_nodes: Option<u64>,
+    /// Depth limit per move for black.
+    #[clap(long)]
+    pub black_depth: Option<u32>,
+    /// Fixed time per move for black, such as `100ms`.
+    #[clap(long, value_parser = clap_parse_duration)]
+    pub black_movetime: Option<Duration>,
+    /// Let black search without a time limit.
+    #[clap(long)]
+    pub black_infinite: bool
+}
+
+impl SideLimitArgs {
+    pub fn white(&self) -> EngineLimits {
+        EngineLimits {
+            tc: self.white_tc.clone(),
+            nps: self.white_nps,
+            nodes: self.white_nodes,
+            depth: self.white_depth,
+            movetime: self.white_movetime,
+            infinite: self.white_infinite
+        }
+    }
+
+    pub fn black(&self) -> EngineLimits {
+        EngineLimits {
+            tc: self.black_tc.clone(),
+            nps: self.black_nps,
+            nodes: self.black_nodes,
+            depth: self.black_depth,
+            movetime: self.black_movetime,
+            infinite: self.black_infinite
+        }
+    }
+}
+
+/// Like [`EngineLimits::time_config`], but exits if `name` has no limits,
+/// or has a node rate without a time control to charge it to.
+pub fn time_config_or_exit(name: &str, limits: &EngineLimits) -> EngineMatchTimeConfig {
+    if limits.nps.is_some() && limits.tc.is_none() {
+        eprintln!("{}: nps given without a time control", name);
+        std::process::exit(1);
+    }
+    limits.time_config().unwrap_or_else(|| {
+        eprintln!("{}: no time control or search limit given", name);
+        std::process::exit(1);
+    })
+}
+
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockArgs {
+    /// Milliseconds an engine may overrun its clock by before losing on time.
+    #[clap(long, default_value_t = 0)]
+    #[serde(rename = "time_margin_ms")]
+    pub time_margin: u64,
+    /// Measure each engine's latency with this many isready pings before every game.
+    #[clap(long, default_value_t = 0)]
+    pub latency_pings: u32,
+    /// Don't charge engines for their measured latency.
+    #[clap(long, requires = "latency-pings")]
+    pub compensate_latency: bool
+}
+
+impl ClockArgs {
+    pub fn time_margin(&self) -> Duration {
+        Duration::from_millis(self.time_margin)
+    }
+}
@@ -1,39 +1,163 @@
 use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use futures_util::StreamExt;
-use cozy_chess::Board;
-use cozy_matches::engine_match::{EngineMatch, EngineMatchConfig, EngineMatchTimeConfig, ChessClockState, EngineMatchEvent};
+use cozy_chess::{Board, Color};
+use cozy_matches::engine_match::{EngineMatch, EngineMatchConfig, EngineMatchEvent, EngineMatchTimeConfig, ChessClockState};
 use cozy_matches::game::ChessGame;
-use cozy_matches::time_control::TimeControl;
+use cozy_matches::lint;
 use serde::{Deserialize, Serialize};
 use clap::{Parser, Subcommand};
 
-use cozy_matches::engine::{Engine, EngineAnalysisEvent};
+use cozy_matches::engine::{Engine, EngineAnalysisEvent, EngineError, Transcript};
+
+use cozy_matches::player::Player;
+
+use tournament::TournamentState;
+use adjudication::AdjudicationArgs;
+use limits::{EngineLimits, SideLimitArgs, ClockArgs};
+
+mod tournament;
+mod replay;
+mod bundle;
+mod adjudication;
+mod limits;
+mod human;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CozyCliConfig {
     engines: BTreeMap<String, EngineConfig>,
 }
 
-fn clap_parse_time_control(s: &str) -> Result<TimeControl, String> {
-    s.parse().map_err(|e| format!("{}", e))
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EngineConfig {
+    path: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    /// File to log the full UCI transcript with this engine to.
+    #[serde(default)]
+    transcript: Option<PathBuf>,
+    /// Default search limits, overriding the ones given for both sides.
+    #[serde(default, flatten)]
+    limits: EngineLimits
+}
+
+async fn spawn_engine(name: &str, config: &EngineConfig) -> Result<Engine, EngineError> {
+    let transcript = config.transcript.as_ref()
+        .map(|path| Transcript::to_file(name.to_owned(), path))
+        .transpose()?;
+    Ok(Engine::new(&config.path, &config.args, transcript).await?.0)
+}
+
+fn clap_parse_color(s: &str) -> Result<Color, String> {
+    match s {
+        "white" => Ok(Color::White),
+        "black" => Ok(Color::Black),
+        _ => Err("expected `white` or `black`".to_owned())
+    }
 }
 
 #[derive(Debug, Parser)]
 struct CozyCliArgs {
+    /// Log the UCI transcript of every engine to `<DIR>/<engine>.log`.
+    #[clap(long, global = true)]
+    debug_log: Option<PathBuf>,
+    /// Where to write reproducer bundles for crashes, time forfeits and illegal moves.
+    #[clap(long, global = true, default_value = "cozy-bundles")]
+    bundle_dir: PathBuf,
     #[clap(subcommand)]
     subcommand: Commands
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
+    /// Play a single game between two engines.
     RunGame {
         #[clap(short, long)]
         white: String,
         #[clap(short, long)]
         black: String,
-        #[clap(long = "tc", value_parser = clap_parse_time_control)]
-        time_control: TimeControl
+        #[clap(flatten)]
+        limits: EngineLimits,
+        #[clap(flatten)]
+        side_limits: SideLimitArgs,
+        #[clap(flatten)]
+        clock: ClockArgs,
+        #[clap(flatten)]
+        adjudication: AdjudicationArgs
+    },
+    /// Play a round robin tournament, saving its progress after every game.
+    Tournament {
+        #[clap(long, multiple_values = true, required_unless_present = "resume")]
+        engines: Vec<String>,
+        #[clap(long, default_value_t = 1)]
+        rounds: u32,
+        #[clap(flatten)]
+        limits: EngineLimits,
+        #[clap(flatten)]
+        clock: ClockArgs,
+        /// File with one opening FEN per line; each opening is played with both colors.
+        #[clap(long)]
+        openings: Option<PathBuf>,
+        /// File the tournament progress is saved to after every game.
+        #[clap(long, required_unless_present = "resume")]
+        state: Option<PathBuf>,
+        /// Continue an interrupted tournament from its state file.
+        #[clap(long, conflicts_with_all = &[
+            "engines", "rounds", "tc", "nps", "nodes", "depth", "movetime", "infinite",
+            "time-margin", "latency-pings", "compensate-latency", "openings", "state",
+            "resign-score", "resign-moves", "draw-score", "draw-moves", "draw-min-move",
+            "draw-reset-on-irreversible", "max-plies", "max-plies-score", "syzygy-path"
+        ])]
+        resume: Option<PathBuf>,
+        /// How many times an engine may be restarted after crashing before the tournament is aborted.
+        #[clap(long, default_value_t = 3)]
+        max_restarts: u32,
+        #[clap(flatten)]
+        adjudication: AdjudicationArgs
+    },
+    /// Re-send the commands of a UCI transcript to an engine and report where its responses diverge.
+    Replay {
+        #[clap(long)]
+        engine: String,
+        transcript: PathBuf,
+        /// Reproduce the original delays between commands.
+        #[clap(long)]
+        pace: bool,
+        /// Only replay the commands sent during this game.
+        #[clap(long)]
+        game: Option<u64>,
+        /// Seconds to wait beyond the recorded response time before reporting a hang.
+        #[clap(long, default_value_t = 10.0)]
+        timeout: f64
+    },
+    /// Play against an engine, entering moves on stdin.
+    Play {
+        #[clap(long)]
+        engine: String,
+        /// The color you play.
+        #[clap(long, value_parser = clap_parse_color)]
+        color: Color,
+        // Limits for the engine; the human plays on its time control, if it has one.
+        #[clap(flatten)]
+        limits: EngineLimits,
+        #[clap(flatten)]
+        clock: ClockArgs
+    },
+    /// Run an engine through UCI protocol scenarios and report where it deviates.
+    UciLint {
+        #[clap(long)]
+        engine: String,
+        /// Print the report as JSON.
+        #[clap(long)]
+        json: bool,
+        /// Seconds to wait for any response before reporting a hang.
+        #[clap(long, default_value_t = 10.0)]
+        timeout: f64,
+        /// Seconds an engine may take to send its best move after `stop`.
+        #[clap(long, default_value_t = 0.1)]
+        stop_timeout: f64
     }
 }
 
@@ -41,45 +165,210 @@ enum Commands {
 async fn main() {
     let args = CozyCliArgs::parse();
     let config = std::fs::read_to_string("cozy-cli-config.json").unwrap();
-    let config: CozyCliConfig = serde_json::from_str(&config).unwrap();
+    let mut config: CozyCliConfig = serde_json::from_str(&config).unwrap_or_else(|e| {
+        eprintln!("cozy-cli-config.json: {}", e);
+        std::process::exit(1);
+    });
+    if let Some(dir) = &args.debug_log {
+        std::fs::create_dir_all(dir).unwrap();
+        for (name, engine) in &mut config.engines {
+            engine.transcript.get_or_insert_with(|| dir.join(format!("{}.log", name)));
+        }
+    }
     
     match args.subcommand {
         Commands::RunGame {
             white,
             black,
-            time_control
+            limits,
+            side_limits,
+            clock,
+            adjudication
         } => {
             let white_config = config.engines.get(&white).unwrap();
             let black_config = config.engines.get(&black).unwrap();
 
-            let white_engine = Engine::new(white_config.clone()).await.unwrap().value;
-            let black_engine = Engine::new(black_config.clone()).await.unwrap().value;
+            let mut white_engine = spawn_engine(&white, white_config).await.unwrap();
+            let mut black_engine = spawn_engine(&black, black_config).await.unwrap();
             
+            let white_limits = side_limits.white().or(&white_config.limits).or(&limits);
+            let black_limits = side_limits.black().or(&black_config.limits).or(&limits);
             let config = EngineMatchConfig {
-                white_time_control: EngineMatchTimeConfig {
-                    search_limit: None,
-                    clock: ChessClockState::Clock(time_control)
-                },
-                black_time_control: EngineMatchTimeConfig {
-                    search_limit: None,
-                    clock: ChessClockState::Clock(time_control)
-                },
+                white_time_control: limits::time_config_or_exit(&white, &white_limits),
+                black_time_control: limits::time_config_or_exit(&black, &black_limits),
+                time_margin: clock.time_margin(),
+                latency_pings: clock.latency_pings,
+                compensate_latency: clock.compensate_latency,
+                adjudicators: adjudication.adjudicators().unwrap()
             };
             let game = ChessGame::new(Board::default());
-            let engine_match = EngineMatch::new(config, game, white_engine, black_engine).unwrap();
+            let engine_match = EngineMatch::new(config, game, &mut white_engine, &mut black_engine).unwrap();
+            let mut game_over = None;
+            {
+                let events = engine_match.run();
+                futures_util::pin_mut!(events);
+                while let Some(event) = events.next().await {
+                    let event = event.unwrap();
+                    match event {
+                        EngineMatchEvent::EngineAnalysisEvent { engine, event } => match event {
+                            EngineAnalysisEvent::Info(_) => {},
+                            EngineAnalysisEvent::BestMove(_) => {},
+                            EngineAnalysisEvent::Resign | EngineAnalysisEvent::Takeback => {},
+                            EngineAnalysisEvent::Stderr(line) => eprintln!("{engine} stderr: {line}"),
+                            EngineAnalysisEvent::EngineError(e) => eprintln!("{engine} engine error: {e}"),
+                        }
+                        EngineMatchEvent::LatencyMeasured { color, latency } => {
+                            println!("{color} latency: {:.3}ms", latency.as_secs_f64() * 1000.0);
+                        }
+                        EngineMatchEvent::SearchStarted { .. } => {}
+                        EngineMatchEvent::MovePlayed { color, mv, elapsed, white_clock, black_clock } => {
+                            let clock = |time: Option<Duration>| time.map_or("-".to_owned(), |t| format!("{:.1}s", t.as_secs_f64()));
+                            println!(
+                                "{color}: {mv} in {:.3}s (white {}, black {})",
+                                elapsed.as_secs_f64(), clock(white_clock), clock(black_clock)
+                            );
+                        }
+                        EngineMatchEvent::GameOver { winner, termination, time_usage } => {
+                            println!("winner: {winner:?} ({termination:?})");
+                            println!("{white}: {}", time_usage[Color::White as usize]);
+                            println!("{black}: {}", time_usage[Color::Black as usize]);
+                            game_over = Some((winner, termination));
+                        }
+                    }
+                }
+            }
+            if let Some((Some(winner), termination)) = game_over {
+                if termination.is_failure() {
+                    let (name, engine_config, engine) = match winner {
+                        Color::White => (&black, black_config, &black_engine),
+                        Color::Black => (&white, white_config, &white_engine),
+                    };
+                    bundle::write_and_report(&args.bundle_dir, name, engine_config, engine, &termination);
+                }
+            }
+        }
+        Commands::Tournament {
+            engines,
+            rounds,
+            limits,
+            clock,
+            openings,
+            state: state_path,
+            resume,
+            max_restarts,
+            adjudication
+        } => {
+            let (state, state_path) = match resume {
+                Some(path) => {
+                    let state = TournamentState::load(&path).unwrap_or_else(|e| {
+                        eprintln!("{}: {}", path.display(), e);
+                        std::process::exit(1);
+                    });
+                    (state, path)
+                }
+                None => {
+                    let openings = match openings {
+                        Some(path) => tournament::read_openings(&path).unwrap(),
+                        None => Vec::new()
+                    };
+                    let state = TournamentState::new(engines, rounds, limits, clock, openings, adjudication);
+                    (state, state_path.unwrap())
+                }
+            };
+            tournament::run(&config, state, &state_path, max_restarts, &args.bundle_dir).await;
+        }
+        Commands::Replay {
+            engine,
+            transcript,
+            pace,
+            game,
+            timeout
+        } => {
+            let engine_config = config.engines.get(&engine).unwrap();
+            let transcript = replay::read_transcript(&transcript, game).unwrap();
+            let options = replay::ReplayOptions {
+                pace,
+                timeout: Duration::from_secs_f64(timeout)
+            };
+            if !replay::run(engine_config, &transcript, options).await {
+                std::process::exit(1);
+            }
+        }
+        Commands::Play {
+            engine,
+            color,
+            limits,
+            clock
+        } => {
+            let engine_config = config.engines.get(&engine).unwrap();
+            let mut engine_player = spawn_engine(&engine, engine_config).await.unwrap();
+            let mut human = human::HumanPlayer::new(color);
+
+            let engine_limits = engine_config.limits.or(&limits);
+            let engine_time_control = limits::time_config_or_exit(&engine, &engine_limits);
+            // The human plays on the engine's clock, but without its search limits.
+            let human_time_control = EngineMatchTimeConfig {
+                search_limit: None,
+                clock: engine_time_control.clock.time_control()
+                    .map_or(ChessClockState::Infinite, |time_control| ChessClockState::Clock(time_control.clone()))
+            };
+            let (white_time_control, black_time_control) = match color {
+                Color::White => (human_time_control, engine_time_control),
+                Color::Black => (engine_time_control, human_time_control)
+            };
+            let config = EngineMatchConfig {
+                white_time_control,
+                black_time_control,
+                time_margin: clock.time_margin(),
+                latency_pings: clock.latency_pings,
+                compensate_latency: clock.compensate_latency,
+                adjudicators: Vec::new()
+            };
+            let (white, black): (&mut dyn Player, &mut dyn Player) = match color {
+                Color::White => (&mut human, &mut engine_player),
+                Color::Black => (&mut engine_player, &mut human)
+            };
+            let game = ChessGame::new(Board::default());
+            let engine_match = EngineMatch::new(config, game, white, black).unwrap();
             let events = engine_match.run();
             futures_util::pin_mut!(events);
             while let Some(event) = events.next().await {
-                let event = event.unwrap();
-                match event {
-                    EngineMatchEvent::EngineAnalysisEvent { engine, event } => match event {
-                        EngineAnalysisEvent::Info(_) => {},
-                        EngineAnalysisEvent::BestMove(mv) => println!("{engine}: {mv}"),
-                        EngineAnalysisEvent::EngineError(e) => todo!("engine error: {}", e),
+                match event.unwrap() {
+                    EngineMatchEvent::EngineAnalysisEvent { engine: side, event: EngineAnalysisEvent::Stderr(line) } => {
+                        eprintln!("{side} stderr: {line}");
                     }
-                    EngineMatchEvent::GameOver { winner } => println!("winner: {winner:?}"),
+                    EngineMatchEvent::GameOver { winner, termination, .. } => {
+                        let result = match winner {
+                            Some(winner) if winner == color => "you win",
+                            Some(_) => "you lose",
+                            None => "draw"
+                        };
+                        println!("{result} ({termination:?})");
+                    }
+                    _ => {}
                 }
             }
         }
+        Commands::UciLint {
+            engine,
+            json,
+            timeout,
+            stop_timeout
+        } => {
+            let engine_config = config.engines.get(&engine).unwrap();
+            let options = lint::LintOptions {
+                timeout: Duration::from_secs_f64(timeout),
+                stop_timeout: Duration::from_secs_f64(stop_timeout)
+            };
+            let report = lint::run(&engine, &engine_config.path, &engine_config.args, &options).await;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                println!("{}", report);
+            }
+            if report.violations() > 0 {
+                std::process::exit(1);
+            }
+        }
     }
 }
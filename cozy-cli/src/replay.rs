@@ -0,0 +1,152 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+use cozy_matches::engine::{RawEngine, EngineError, TranscriptEntry, TranscriptDirection};
+
+use crate::EngineConfig;
+
+pub struct ReplayOptions {
+    pub pace: bool,
+    pub timeout: Duration
+}
+
+enum Divergence {
+    BestMove {
+        recorded: String,
+        actual: String
+    },
+    Hang {
+        token: String,
+        waited: Duration
+    },
+    Error(EngineError)
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BestMove { recorded, actual } => write!(f, "bestmove {} (recorded bestmove {})", actual, recorded),
+            Self::Hang { token, waited } => write!(f, "no {} after {:.3}s", token, waited.as_secs_f64()),
+            Self::Error(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+/// Remarks that conclude a command. Replay waits for each of these before
+/// sending further commands, just like the original run did.
+fn terminal_token(line: &str) -> Option<&str> {
+    let token = line.split_whitespace().next()?;
+    matches!(token, "uciok" | "readyok" | "bestmove").then_some(token)
+}
+
+pub fn read_transcript(path: &Path, game: Option<u64>) -> std::io::Result<Vec<(usize, TranscriptEntry)>> {
+    let mut entries = Vec::new();
+    for (i, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        let entry: TranscriptEntry = line.parse().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+        })?;
+        let in_game = match game {
+            Some(game) => entry.game == Some(game),
+            None => true
+        };
+        if in_game {
+            entries.push((i + 1, entry));
+        }
+    }
+    Ok(entries)
+}
+
+async fn wait_for(engine: &mut RawEngine, token: &str, limit: Duration) -> Result<String, Divergence> {
+    let wait = async {
+        loop {
+            let line = engine.recv_line().await?;
+            if line.split_whitespace().next() == Some(token) {
+                return Ok::<_, EngineError>(line);
+            }
+        }
+    };
+    match tokio::time::timeout(limit, wait).await {
+        Ok(result) => result.map_err(Divergence::Error),
+        Err(_) => Err(Divergence::Hang { token: token.to_owned(), waited: limit })
+    }
+}
+
+fn report(line_number: usize, divergence: &Divergence) {
+    println!("line {}: {}", line_number, divergence);
+    if let Divergence::Error(EngineError::UnexpectedTermination(exit)) = divergence {
+        for line in &exit.stderr {
+            println!("    {}", line);
+        }
+    }
+}
+
+/// Re-sends every command of a transcript to the engine and compares its
+/// responses to the recorded ones. Returns whether the replay matched.
+pub async fn run(config: &EngineConfig, transcript: &[(usize, TranscriptEntry)], options: ReplayOptions) -> bool {
+    let mut engine = RawEngine::new(&config.path, &config.args, None).await.unwrap();
+    let mut commands = 0;
+    let mut divergences = 0;
+
+    // A transcript cut down to a single game lacks the handshake.
+    let has_handshake = transcript.iter()
+        .any(|(_, e)| e.direction == TranscriptDirection::Sent && e.line.trim() == "uci");
+    if !has_handshake {
+        let handshake = match engine.send_line("uci\n").await {
+            Ok(()) => wait_for(&mut engine, "uciok", options.timeout).await.map(drop),
+            Err(e) => Err(Divergence::Error(e))
+        };
+        if let Err(divergence) = handshake {
+            report(0, &divergence);
+            return false;
+        }
+    }
+
+    let first_time = transcript.first().map_or(Duration::ZERO, |(_, e)| e.time);
+    let start = tokio::time::Instant::now();
+    let mut last_sent = first_time;
+    for (line_number, entry) in transcript {
+        match entry.direction {
+            TranscriptDirection::Sent => {
+                if options.pace {
+                    tokio::time::sleep_until(start + entry.time.saturating_sub(first_time)).await;
+                }
+                last_sent = entry.time;
+                if let Err(e) = engine.send_line(&format!("{}\n", entry.line)).await {
+                    report(*line_number, &Divergence::Error(e));
+                    return false;
+                }
+                commands += 1;
+            }
+            TranscriptDirection::Received => {
+                let token = match terminal_token(&entry.line) {
+                    Some(token) => token,
+                    None => continue
+                };
+                let limit = entry.time.saturating_sub(last_sent) + options.timeout;
+                let line = match wait_for(&mut engine, token, limit).await {
+                    Ok(line) => line,
+                    Err(divergence) => {
+                        report(*line_number, &divergence);
+                        return false;
+                    }
+                };
+                // A recorded bestmove without a move only waits for the search.
+                let recorded = entry.line.split_whitespace().nth(1);
+                let actual = line.split_whitespace().nth(1);
+                if let (Some(recorded), "bestmove") = (recorded, token) {
+                    if Some(recorded) != actual {
+                        divergences += 1;
+                        report(*line_number, &Divergence::BestMove {
+                            recorded: recorded.to_owned(),
+                            actual: actual.unwrap_or_default().to_owned()
+                        });
+                    }
+                }
+            }
+            TranscriptDirection::Stderr => {}
+        }
+    }
+    println!("replayed {} commands, {} divergences", commands, divergences);
+    divergences == 0
+}
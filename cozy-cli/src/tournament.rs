@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::path::Path;
+
+use futures_util::StreamExt;
+use cozy_chess::{Board, Color};
+use cozy_matches::engine::{Engine, EngineAnalysisEvent};
+use cozy_matches::engine_match::{EngineMatch, EngineMatchConfig, EngineMatchEvent, GameTermination};
+use cozy_matches::game::ChessGame;
+use cozy_matches::player::Player;
+use cozy_matches::time_usage::TimeUsage;
+use serde::{Deserialize, Serialize};
+
+use crate::{CozyCliConfig, bundle, limits, spawn_engine};
+use crate::adjudication::AdjudicationArgs;
+use crate::limits::{EngineLimits, ClockArgs};
+
+/// Everything needed to continue a tournament after an interruption.
+/// The schedule is fixed up front, so a resumed run plays the exact same
+/// pairings with the exact same openings.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TournamentState {
+    pub engines: Vec<String>,
+    #[serde(flatten)]
+    pub limits: EngineLimits,
+    #[serde(flatten)]
+    pub clock: ClockArgs,
+    pub openings: Vec<String>,
+    #[serde(default)]
+    pub adjudication: AdjudicationArgs,
+    pub schedule: Vec<ScheduledGame>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduledGame {
+    pub white: String,
+    pub black: String,
+    /// Index into [`TournamentState::openings`], or `None` for the start position.
+    pub opening: Option<usize>,
+    pub result: Option<GameResult>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameResult {
+    #[serde(rename = "1-0")]
+    WhiteWin,
+    #[serde(rename = "0-1")]
+    BlackWin,
+    #[serde(rename = "1/2-1/2")]
+    Draw
+}
+
+impl GameResult {
+    pub fn from_winner(winner: Option<Color>) -> Self {
+        match winner {
+            Some(Color::White) => Self::WhiteWin,
+            Some(Color::Black) => Self::BlackWin,
+            None => Self::Draw
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WhiteWin => write!(f, "1-0"),
+            Self::BlackWin => write!(f, "0-1"),
+            Self::Draw => write!(f, "1/2-1/2")
+        }
+    }
+}
+
+impl TournamentState {
+    /// Schedules a round robin where every pair of engines plays each
+    /// opening twice, once with either color. Openings are used in file
+    /// order and wrap around if there are fewer openings than pairings.
+    pub fn new(engines: Vec<String>, rounds: u32, limits: EngineLimits, clock: ClockArgs, mut openings: Vec<String>, adjudication: AdjudicationArgs) -> Self {
+        let pairings = engines.len() * engines.len().saturating_sub(1) / 2 * rounds as usize;
+        openings.truncate(pairings);
+
+        let mut schedule = Vec::new();
+        let mut opening_cursor = 0;
+        for _ in 0..rounds {
+            for (i, first) in engines.iter().enumerate() {
+                for second in &engines[i + 1..] {
+                    let opening = if openings.is_empty() {
+                        None
+                    } else {
+                        Some(opening_cursor % openings.len())
+                    };
+                    opening_cursor += 1;
+                    for (white, black) in [(first, second), (second, first)] {
+                        schedule.push(ScheduledGame {
+                            white: white.clone(),
+                            black: black.clone(),
+                            opening,
+                            result: None
+                        });
+                    }
+                }
+            }
+        }
+        Self {
+            engines,
+            limits,
+            clock,
+            openings,
+            adjudication,
+            schedule
+        }
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the state to a temporary file first so that a crash mid-write
+    /// never leaves a truncated state file behind.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(temp_path, path)
+    }
+
+    fn opening(&self, game: &ScheduledGame) -> Board {
+        match game.opening {
+            Some(index) => Board::from_fen(&self.openings[index], false).unwrap(),
+            None => Board::default()
+        }
+    }
+}
+
+pub fn read_openings(path: &Path) -> std::io::Result<Vec<String>> {
+    let openings = std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|fen| {
+            Board::from_fen(fen, false)
+                .map(|_| fen.to_owned())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", fen, e)))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(openings)
+}
+
+/// Engines are kept alive between games and respawned from their config
+/// if they crash, up to `max_restarts` times each.
+struct EnginePool<'c> {
+    config: &'c CozyCliConfig,
+    engines: BTreeMap<String, Engine>,
+    restarts: BTreeMap<String, u32>,
+    max_restarts: u32
+}
+
+impl<'c> EnginePool<'c> {
+    fn new(config: &'c CozyCliConfig, max_restarts: u32) -> Self {
+        Self {
+            config,
+            engines: BTreeMap::new(),
+            restarts: BTreeMap::new(),
+            max_restarts
+        }
+    }
+
+    async fn take(&mut self, name: &str, game: u64) -> Engine {
+        let engine_config = self.config.engines.get(name).unwrap();
+        let mut engine = match self.engines.remove(name) {
+            Some(engine) => Ok(engine),
+            None => spawn_engine(name, engine_config).await
+        };
+        loop {
+            let e = match engine {
+                Ok(mut engine) => {
+                    if let Some(transcript) = engine.transcript() {
+                        transcript.set_game(Some(game));
+                    }
+                    // The match starts the new game; this only checks the engine is still alive.
+                    match engine.ping().await {
+                        Ok(_) => return engine,
+                        Err(e) => e
+                    }
+                }
+                Err(e) => e
+            };
+            let restarts = self.restarts.entry(name.to_owned()).or_default();
+            if *restarts >= self.max_restarts {
+                eprintln!("{}: {}; exceeded {} restarts, aborting", name, e, self.max_restarts);
+                std::process::exit(1);
+            }
+            *restarts += 1;
+            eprintln!("{}: {}; restarting ({}/{})", name, e, restarts, self.max_restarts);
+            engine = spawn_engine(name, engine_config).await;
+        }
+    }
+
+    fn put_back(&mut self, name: String, engine: Engine) {
+        self.engines.insert(name, engine);
+    }
+}
+
+pub async fn run(config: &CozyCliConfig, mut state: TournamentState, state_path: &Path, max_restarts: u32, bundle_dir: &Path) {
+    let adjudicators = state.adjudication.adjudicators().unwrap();
+    let mut engines = EnginePool::new(config, max_restarts);
+    // Only covers the games played in this run, not ones before a resume.
+    let mut time_usage: BTreeMap<String, TimeUsage> = BTreeMap::new();
+    // Latency is measured every game, but only reported the first time.
+    let mut latency_reported = BTreeSet::new();
+
+    for name in &state.engines {
+        let limits = config.engines[name].limits.or(&state.limits);
+        if let Some(time_control) = limits::time_config_or_exit(name, &limits).clock.time_control() {
+            println!("{}: time control {}", name, time_control);
+        }
+    }
+
+    let total = state.schedule.len();
+    for index in 0..total {
+        let scheduled = &state.schedule[index];
+        if scheduled.result.is_some() {
+            continue;
+        }
+        let board = state.opening(scheduled);
+        let white_name = scheduled.white.clone();
+        let black_name = scheduled.black.clone();
+
+        // A crashed engine fails its liveness check and gets respawned here.
+        let game = index as u64 + 1;
+        let mut white = engines.take(&white_name, game).await;
+        let mut black = engines.take(&black_name, game).await;
+        let white_limits = config.engines[&white_name].limits.or(&state.limits);
+        let black_limits = config.engines[&black_name].limits.or(&state.limits);
+        let match_config = EngineMatchConfig {
+            white_time_control: limits::time_config_or_exit(&white_name, &white_limits),
+            black_time_control: limits::time_config_or_exit(&black_name, &black_limits),
+            time_margin: state.clock.time_margin(),
+            latency_pings: state.clock.latency_pings,
+            compensate_latency: state.clock.compensate_latency,
+            adjudicators: adjudicators.clone()
+        };
+        let (result, termination, [white_usage, black_usage]) = play_game(match_config, board, [&white_name, &black_name], &mut white, &mut black, &mut latency_reported).await;
+        if termination.is_failure() {
+            let (name, engine) = match result {
+                GameResult::WhiteWin => (&black_name, &black),
+                _ => (&white_name, &white)
+            };
+            bundle::write_and_report(bundle_dir, name, &config.engines[name], engine, &termination);
+        }
+        time_usage.entry(white_name.clone()).or_default().merge(&white_usage);
+        time_usage.entry(black_name.clone()).or_default().merge(&black_usage);
+        engines.put_back(white_name.clone(), white);
+        engines.put_back(black_name.clone(), black);
+
+        print!("game {}/{}: {} vs {}: {}", index + 1, total, white_name, black_name, result);
+        match &termination {
+            GameTermination::Normal => println!(),
+            GameTermination::Crash { exit } => {
+                println!(" (crash: {})", exit);
+                for line in &exit.stderr {
+                    println!("    {}", line);
+                }
+            }
+            _ => println!(" ({:?})", termination)
+        }
+        state.schedule[index].result = Some(result);
+        state.save(state_path).unwrap();
+    }
+    print_standings(&state);
+    for (name, usage) in &time_usage {
+        println!("{} time usage: {}", name, usage);
+    }
+}
+
+async fn play_game<P: Player + ?Sized>(
+    config: EngineMatchConfig,
+    board: Board,
+    names: [&str; Color::NUM],
+    white: &mut P,
+    black: &mut P,
+    latency_reported: &mut BTreeSet<String>
+) -> (GameResult, GameTermination, [TimeUsage; Color::NUM]) {
+    let engine_match = EngineMatch::new(config, ChessGame::new(board), white, black).unwrap();
+    let events = engine_match.run();
+    futures_util::pin_mut!(events);
+    let mut result = None;
+    let mut searching = Color::White;
+    while let Some(event) = events.next().await {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                // Blame the engine that was searching, as for engine errors the match handles itself.
+                let termination = GameTermination::EngineError { error: e.to_string() };
+                result = Some((GameResult::from_winner(Some(!searching)), termination, Default::default()));
+                break;
+            }
+        };
+        match event {
+            EngineMatchEvent::SearchStarted { color, .. } => searching = color,
+            EngineMatchEvent::LatencyMeasured { color, latency } => {
+                let name = names[color as usize];
+                if latency_reported.insert(name.to_owned()) {
+                    println!("{} latency: {:.3}ms", name, latency.as_secs_f64() * 1000.0);
+                }
+            }
+            EngineMatchEvent::EngineAnalysisEvent { engine, event: EngineAnalysisEvent::Stderr(line) } => {
+                eprintln!("{} stderr: {}", names[engine as usize], line);
+            }
+            EngineMatchEvent::GameOver { winner, termination, time_usage } => {
+                result = Some((GameResult::from_winner(winner), termination, time_usage));
+            }
+            _ => {}
+        }
+    }
+    result.unwrap()
+}
+
+fn print_standings(state: &TournamentState) {
+    let mut standings: BTreeMap<&str, [u32; 3]> = state.engines.iter()
+        .map(|name| (name.as_str(), [0; 3]))
+        .collect();
+    for game in &state.schedule {
+        let (white, black) = match game.result {
+            Some(GameResult::WhiteWin) => (0, 2),
+            Some(GameResult::BlackWin) => (2, 0),
+            Some(GameResult::Draw) => (1, 1),
+            None => continue
+        };
+        standings.get_mut(game.white.as_str()).unwrap()[white] += 1;
+        standings.get_mut(game.black.as_str()).unwrap()[black] += 1;
+    }
+    for (name, [wins, draws, losses]) in standings {
+        let score = wins as f32 + draws as f32 / 2.0;
+        println!("{}: +{} ={} -{} ({}/{})", name, wins, draws, losses, score, wins + draws + losses);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Move;
+    use cozy_matches::engine_match::{ChessClockState, EngineMatchTimeConfig};
+    use cozy_matches::player::ScriptedPlayer;
+
+    use super::*;
+
+    fn scripted(moves: &[&str], score: Option<i32>) -> ScriptedPlayer {
+        let moves = moves.iter()
+            .map(|mv| (mv.parse::<Move>().unwrap(), score))
+            .collect();
+        ScriptedPlayer::with_scores(moves)
+    }
+
+    async fn play(white: &mut ScriptedPlayer, black: &mut ScriptedPlayer, adjudication: AdjudicationArgs) -> (GameResult, GameTermination) {
+        let time_control = || EngineMatchTimeConfig {
+            search_limit: None,
+            clock: ChessClockState::Infinite
+        };
+        let config = EngineMatchConfig {
+            white_time_control: time_control(),
+            black_time_control: time_control(),
+            time_margin: Default::default(),
+            latency_pings: 0,
+            compensate_latency: false,
+            adjudicators: adjudication.adjudicators().unwrap()
+        };
+        let mut latency_reported = BTreeSet::new();
+        let (result, termination, _) = play_game(config, Board::default(), ["white", "black"], white, black, &mut latency_reported).await;
+        (result, termination)
+    }
+
+    #[tokio::test]
+    async fn checkmate() {
+        let mut white = scripted(&["f2f3", "g2g4"], None);
+        let mut black = scripted(&["e7e5", "d8h4"], None);
+        let (result, termination) = play(&mut white, &mut black, AdjudicationArgs::default()).await;
+        assert_eq!(result, GameResult::BlackWin);
+        assert!(matches!(termination, GameTermination::Normal));
+    }
+
+    #[tokio::test]
+    async fn resign_adjudication() {
+        let mut white = scripted(&["e2e4", "g1f3", "f1c4"], Some(-600));
+        let mut black = scripted(&["e7e5", "b8c6", "f8c5"], Some(600));
+        let adjudication = AdjudicationArgs {
+            resign_score: Some(500),
+            resign_moves: 2,
+            ..Default::default()
+        };
+        let (result, termination) = play(&mut white, &mut black, adjudication).await;
+        assert_eq!(result, GameResult::BlackWin);
+        assert!(matches!(termination, GameTermination::Adjudication));
+    }
+
+    #[tokio::test]
+    async fn max_plies_draw() {
+        let mut white = scripted(&["e2e4", "g1f3"], None);
+        let mut black = scripted(&["e7e5", "b8c6"], None);
+        let adjudication = AdjudicationArgs {
+            max_plies: Some(4),
+            ..Default::default()
+        };
+        let (result, termination) = play(&mut white, &mut black, adjudication).await;
+        assert_eq!(result, GameResult::Draw);
+        assert!(matches!(termination, GameTermination::MaxMoves));
+    }
+}
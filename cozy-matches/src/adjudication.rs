@@ -0,0 +1,187 @@
+use std::fmt::Debug;
+
+use cozy_chess::*;
+use cozy_uci::remark::{UciInfo, UciScore, UciScoreKind};
+
+use crate::clock::ChessClock;
+use crate::engine_match::GameTermination;
+use crate::game::ChessGame;
+
+const MATE_SCORE: i32 = 100_000;
+
+/// Converts a score to centipawns, mapping mate scores beyond any real evaluation.
+pub fn score_to_cp(score: &UciScore) -> i32 {
+    match score.kind {
+        UciScoreKind::Centipawns(cp) => cp,
+        UciScoreKind::Mate(moves) if moves > 0 => MATE_SCORE,
+        UciScoreKind::Mate(_) => -MATE_SCORE
+    }
+}
+
+/// The state an [`Adjudicator`] gets to look at after each move.
+#[derive(Debug, Clone, Copy)]
+pub struct AdjudicationContext<'a> {
+    /// The game, including the move just played.
+    pub game: &'a ChessGame,
+    /// Both sides' clocks after the move.
+    pub clock: &'a ChessClock,
+    /// The last info with a score each engine sent during its latest search.
+    pub infos: [Option<&'a UciInfo>; Color::NUM]
+}
+
+impl AdjudicationContext<'_> {
+    /// The side that played the last move.
+    pub fn mover(&self) -> Color {
+        !self.game.board().side_to_move()
+    }
+
+    /// `color`'s latest score in centipawns, from its own point of view.
+    pub fn score(&self, color: Color) -> Option<i32> {
+        self.infos[color as usize]?.score.as_ref().map(score_to_cp)
+    }
+}
+
+/// A rule that can end a game early. Adjudicators are cloned along with the
+/// [`EngineMatchConfig`](crate::engine_match::EngineMatchConfig), so each
+/// game starts from a fresh copy.
+pub trait Adjudicator: AdjudicatorClone + Debug + Send {
+    /// Called after every move that didn't end the game. Returns the winner
+    /// and termination to end the game with.
+    fn adjudicate(&mut self, context: &AdjudicationContext) -> Option<(Option<Color>, GameTermination)>;
+}
+
+pub trait AdjudicatorClone {
+    fn clone_box(&self) -> Box<dyn Adjudicator>;
+}
+
+impl<A: Adjudicator + Clone + 'static> AdjudicatorClone for A {
+    fn clone_box(&self) -> Box<dyn Adjudicator> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Adjudicator> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResignAdjudication {
+    /// Centipawns a side has to be behind by.
+    pub score: i32,
+    /// Consecutive moves each engine has to agree for.
+    pub move_count: u32
+}
+
+/// Declares a winner once both engines agree on one.
+#[derive(Debug, Clone)]
+pub struct ResignAdjudicator {
+    config: ResignAdjudication,
+    losing: [u32; Color::NUM],
+    winning: [u32; Color::NUM]
+}
+
+impl ResignAdjudicator {
+    pub fn new(config: ResignAdjudication) -> Self {
+        Self {
+            config,
+            losing: [0; Color::NUM],
+            winning: [0; Color::NUM]
+        }
+    }
+}
+
+impl Adjudicator for ResignAdjudicator {
+    fn adjudicate(&mut self, context: &AdjudicationContext) -> Option<(Option<Color>, GameTermination)> {
+        let color = context.mover();
+        let score = context.score(color);
+        let extend = |streak: &mut u32, agrees: bool| *streak = if agrees { *streak + 1 } else { 0 };
+        extend(&mut self.losing[color as usize], matches!(score, Some(s) if s <= -self.config.score));
+        extend(&mut self.winning[color as usize], matches!(score, Some(s) if s >= self.config.score));
+
+        let move_count = self.config.move_count.max(1);
+        Color::ALL.into_iter()
+            .find(|&winner| {
+                self.winning[winner as usize] >= move_count && self.losing[!winner as usize] >= move_count
+            })
+            .map(|winner| (Some(winner), GameTermination::Adjudication))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DrawAdjudication {
+    /// Full move number before which no draw is adjudicated.
+    pub min_move: u32,
+    /// Centipawns both engines' scores have to stay within.
+    pub score: i32,
+    /// Consecutive move pairs the scores have to stay within `score` for.
+    pub move_count: u32,
+    /// Whether captures and pawn moves restart the count.
+    pub reset_on_irreversible: bool
+}
+
+/// Declares a draw once both engines' scores have stayed close to zero.
+#[derive(Debug, Clone)]
+pub struct DrawAdjudicator {
+    config: DrawAdjudication,
+    plies: u32
+}
+
+impl DrawAdjudicator {
+    pub fn new(config: DrawAdjudication) -> Self {
+        Self { config, plies: 0 }
+    }
+}
+
+impl Adjudicator for DrawAdjudicator {
+    fn adjudicate(&mut self, context: &AdjudicationContext) -> Option<(Option<Color>, GameTermination)> {
+        let board = context.game.board();
+        let score = context.score(context.mover());
+        let irreversible = self.config.reset_on_irreversible && board.halfmove_clock() == 0;
+        if irreversible || !matches!(score, Some(s) if s.abs() <= self.config.score) {
+            self.plies = 0;
+        } else {
+            self.plies += 1;
+        }
+        let draw = board.fullmove_number() as u32 >= self.config.min_move
+            && self.plies >= self.config.move_count.max(1) * 2;
+        draw.then_some((None, GameTermination::Adjudication))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MaxMovesAdjudication {
+    /// Plies after which the game is stopped.
+    pub max_plies: u32,
+    /// If set, a side whose last score is at least this many centipawns
+    /// ahead wins instead of the game being drawn.
+    pub score: Option<i32>
+}
+
+/// Stops the game once it reaches a number of plies.
+#[derive(Debug, Clone)]
+pub struct MaxMovesAdjudicator {
+    config: MaxMovesAdjudication
+}
+
+impl MaxMovesAdjudicator {
+    pub fn new(config: MaxMovesAdjudication) -> Self {
+        Self { config }
+    }
+}
+
+impl Adjudicator for MaxMovesAdjudicator {
+    fn adjudicate(&mut self, context: &AdjudicationContext) -> Option<(Option<Color>, GameTermination)> {
+        if (context.game.stack().len() as u32) < self.config.max_plies {
+            return None;
+        }
+        let color = context.mover();
+        let winner = match (self.config.score, context.score(color)) {
+            (Some(threshold), Some(score)) if score >= threshold => Some(color),
+            (Some(threshold), Some(score)) if score <= -threshold => Some(!color),
+            _ => None
+        };
+        Some((winner, GameTermination::MaxMoves))
+    }
+}
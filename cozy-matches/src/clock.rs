@@ -0,0 +1,239 @@
+use std::time::Duration;
+
+use cozy_chess::*;
+
+use crate::engine::AnalysisTimeLimit;
+use crate::engine_match::ChessClockState;
+
+#[derive(Debug, Clone)]
+struct SideClock {
+    state: ChessClockState,
+    time: Duration,
+    /// Index of the current period of the time control.
+    period: usize,
+    /// Moves played in the current period.
+    period_moves: u32,
+    moves: u32
+}
+
+impl SideClock {
+    fn new(state: ChessClockState) -> Self {
+        let time = state.time_control().map_or(Duration::ZERO, |time_control| time_control.periods[0].time);
+        Self {
+            state,
+            time,
+            period: 0,
+            period_moves: 0,
+            moves: 0
+        }
+    }
+}
+
+/// The outcome of charging a side for a move.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockUpdate {
+    /// Whether the side ran out of time.
+    pub flagged: bool,
+    /// The time left right after the move, before the time of the next
+    /// period is added. `None` if the side has no time control.
+    pub time_left: Option<Duration>
+}
+
+/// Both sides' clocks over the course of a game.
+#[derive(Debug, Clone)]
+pub struct ChessClock {
+    sides: [SideClock; Color::NUM],
+    /// How far a side may overrun its time before it flags.
+    margin: Duration
+}
+
+impl ChessClock {
+    pub fn new(white: ChessClockState, black: ChessClockState, margin: Duration) -> Self {
+        Self {
+            sides: [SideClock::new(white), SideClock::new(black)],
+            margin
+        }
+    }
+
+    pub fn state(&self, color: Color) -> &ChessClockState {
+        &self.sides[color as usize].state
+    }
+
+    /// Moves `color` has played so far.
+    pub fn moves(&self, color: Color) -> u32 {
+        self.sides[color as usize].moves
+    }
+
+    pub fn time_left(&self, color: Color) -> Option<Duration> {
+        let side = &self.sides[color as usize];
+        side.state.time_control().map(|_| side.time)
+    }
+
+    /// The increment of the current period. UCI has no notion of delays, so
+    /// delays are reported as increments, which is the time an engine can
+    /// spend on each move without its clock running down.
+    pub fn increment(&self, color: Color) -> Option<Duration> {
+        let side = &self.sides[color as usize];
+        side.state.time_control().map(|time_control| time_control.periods[side.period].increment)
+    }
+
+    /// Moves left until the next period starts, if the current one has a move limit.
+    pub fn moves_to_go(&self, color: Color) -> Option<u32> {
+        let side = &self.sides[color as usize];
+        let time_control = side.state.time_control()?;
+        time_control.periods[side.period].moves.map(|limit| limit - side.period_moves)
+    }
+
+    /// The time limit to search `color`'s next move with.
+    pub fn time_limit(&self, color: Color) -> AnalysisTimeLimit {
+        match self.state(color) {
+            ChessClockState::Infinite => AnalysisTimeLimit::Infinite,
+            ChessClockState::MoveTime(move_time) => AnalysisTimeLimit::MoveTime(*move_time),
+            ChessClockState::Clock(_) | ChessClockState::NodesAsTime { .. } => AnalysisTimeLimit::TimeLeft {
+                white_time: self.time_left(Color::White),
+                black_time: self.time_left(Color::Black),
+                white_increment: self.increment(Color::White),
+                black_increment: self.increment(Color::Black),
+                moves_to_go: self.moves_to_go(color)
+            }
+        }
+    }
+
+    /// Charges `color` for a move that took `elapsed`, then adds its increment.
+    /// For [`ChessClockState::NodesAsTime`], `elapsed` should come from
+    /// [`ChessClockState::charged_time`].
+    pub fn update(&mut self, color: Color, elapsed: Duration) -> ClockUpdate {
+        let margin = self.margin;
+        let side = &mut self.sides[color as usize];
+        side.moves += 1;
+        match &side.state {
+            ChessClockState::Infinite => ClockUpdate { flagged: false, time_left: None },
+            ChessClockState::MoveTime(move_time) => ClockUpdate {
+                flagged: elapsed > *move_time + margin,
+                time_left: None
+            },
+            ChessClockState::Clock(time_control) | ChessClockState::NodesAsTime { time_control, .. } => {
+                let current = time_control.periods[side.period];
+                let (charged, refund) = current.increment_kind.charge(elapsed, current.increment);
+                let flagged = charged > side.time + margin;
+                side.time = side.time.saturating_sub(charged) + refund;
+                let time_left = Some(side.time);
+                side.period_moves += 1;
+                if Some(side.period_moves) == current.moves {
+                    side.period = time_control.next_period(side.period);
+                    side.time += time_control.periods[side.period].time;
+                    side.period_moves = 0;
+                }
+                ClockUpdate { flagged, time_left }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_clock(time_control: &str, margin: Duration) -> ChessClock {
+        let state = ChessClockState::Clock(time_control.parse().unwrap());
+        ChessClock::new(state.clone(), state, margin)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn fischer_deducts_before_increment() {
+        let mut clock = new_clock("1+1", Duration::ZERO);
+        assert!(clock.update(Color::White, ms(1500)).flagged);
+
+        let mut clock = new_clock("1+1", Duration::ZERO);
+        assert!(!clock.update(Color::White, ms(600)).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(ms(1400)));
+        assert_eq!(clock.time_left(Color::Black), Some(ms(1000)));
+    }
+
+    #[test]
+    fn margin_allows_small_overruns() {
+        let mut clock = new_clock("1+0", ms(50));
+        assert!(!clock.update(Color::White, ms(1030)).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(Duration::ZERO));
+        assert!(clock.update(Color::Black, ms(1051)).flagged);
+    }
+
+    #[test]
+    fn bronstein_refunds_up_to_delay() {
+        let mut clock = new_clock("10+b2", Duration::ZERO);
+        assert!(!clock.update(Color::White, ms(1500)).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(ms(10000)));
+        assert!(!clock.update(Color::White, ms(3000)).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(ms(9000)));
+        assert!(clock.update(Color::White, ms(9500)).flagged);
+    }
+
+    #[test]
+    fn simple_delay_starts_late() {
+        let mut clock = new_clock("10+d2", Duration::ZERO);
+        assert!(!clock.update(Color::White, ms(1500)).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(ms(10000)));
+        assert!(!clock.update(Color::White, ms(11500)).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(ms(500)));
+        assert!(clock.update(Color::White, ms(2600)).flagged);
+    }
+
+    #[test]
+    fn periods_add_time_and_count_down_moves() {
+        let mut clock = new_clock("2/10:5+1", Duration::ZERO);
+        assert_eq!(clock.moves_to_go(Color::White), Some(2));
+        clock.update(Color::White, ms(1000));
+        assert_eq!(clock.moves_to_go(Color::White), Some(1));
+        clock.update(Color::White, ms(1000));
+        assert_eq!(clock.moves_to_go(Color::White), None);
+        assert_eq!(clock.time_left(Color::White), Some(ms(13000)));
+        assert_eq!(clock.increment(Color::White), Some(ms(1000)));
+        assert_eq!(clock.moves(Color::White), 2);
+        assert_eq!(clock.moves(Color::Black), 0);
+    }
+
+    #[test]
+    fn update_reports_time_before_next_period() {
+        let mut clock = new_clock("2/10:5", Duration::ZERO);
+        assert_eq!(clock.update(Color::White, ms(3000)).time_left, Some(ms(7000)));
+        assert_eq!(clock.update(Color::White, ms(3000)).time_left, Some(ms(4000)));
+        assert_eq!(clock.time_left(Color::White), Some(ms(9000)));
+    }
+
+    #[test]
+    fn last_period_repeats() {
+        let mut clock = new_clock("1/10", Duration::ZERO);
+        clock.update(Color::Black, ms(4000));
+        assert_eq!(clock.time_left(Color::Black), Some(ms(16000)));
+        assert_eq!(clock.moves_to_go(Color::Black), Some(1));
+    }
+
+    #[test]
+    fn move_time_flags_on_overrun() {
+        let state = ChessClockState::MoveTime(ms(100));
+        let mut clock = ChessClock::new(state, ChessClockState::Infinite, ms(10));
+        assert!(!clock.update(Color::White, ms(110)).flagged);
+        assert!(clock.update(Color::White, ms(111)).flagged);
+        assert!(!clock.update(Color::Black, ms(1_000_000)).flagged);
+        assert_eq!(clock.time_left(Color::White), None);
+    }
+
+    #[test]
+    fn nodes_as_time_charges_nodes() {
+        let state = ChessClockState::NodesAsTime {
+            time_control: "10+1".parse().unwrap(),
+            nps: 1000
+        };
+        let charged = state.charged_time(ms(5), Some(2500));
+        assert_eq!(charged, ms(2500));
+        assert_eq!(state.charged_time(ms(5), None), ms(5));
+
+        let mut clock = ChessClock::new(state.clone(), state, Duration::ZERO);
+        assert!(!clock.update(Color::White, charged).flagged);
+        assert_eq!(clock.time_left(Color::White), Some(ms(8500)));
+    }
+}
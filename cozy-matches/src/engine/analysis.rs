@@ -32,10 +32,24 @@ pub enum AnalysisTimeLimit {
     }
 }
 
+/// The exact commands that started the most recent search.
+#[derive(Debug, Clone)]
+pub struct SearchRecord {
+    pub board: Board,
+    pub limit: AnalysisLimit,
+    pub position: String,
+    pub go: String
+}
+
 #[derive(Debug)]
 pub enum EngineAnalysisEvent {
     Info(UciInfo),
     BestMove(Move),
+    /// The player resigns instead of moving.
+    Resign,
+    /// The player takes back its last move and the reply to it, then moves again.
+    Takeback,
+    Stderr(String),
     EngineError(EngineError)
 }
 
@@ -43,6 +57,12 @@ pub struct EngineAnalysis<'s> {
     pub(super) stream: Pin<Box<dyn Stream<Item = Result<EngineAnalysisEvent, EngineError>> + 's>>
 }
 
+impl<'s> EngineAnalysis<'s> {
+    pub fn new(stream: impl Stream<Item = Result<EngineAnalysisEvent, EngineError>> + 's) -> Self {
+        Self { stream: Box::pin(stream) }
+    }
+}
+
 impl<'s> Stream for EngineAnalysis<'s> {
     type Item = Result<EngineAnalysisEvent, EngineError>;
 
@@ -1,3 +1,6 @@
+use std::fmt::Display;
+use std::process::ExitStatus;
+
 use thiserror::Error;
 use cozy_uci::UciParseError;
 use cozy_uci::remark::UciRemark;
@@ -6,9 +9,9 @@ use cozy_uci::remark::UciRemark;
 pub enum EngineError {
     #[error("io error: {0}")]
     IoError(#[from] tokio::io::Error),
-    #[error("engine unexpectedly exited")]
-    UnexpectedTermination,
-    #[error("invalid message")]
+    #[error("engine unexpectedly exited ({0})")]
+    UnexpectedTermination(EngineExit),
+    #[error("invalid message {0:?}")]
     InvalidMessage(String, UciParseError),
     #[error("unexpected remark")]
     UnexpectedRemark(UciRemark),
@@ -17,7 +20,27 @@ pub enum EngineError {
     #[error("missing author")]
     MissingAuthor,
     #[error("invalid option")]
-    InvalidOption
+    InvalidOption,
+    #[error("no response within {0:?}")]
+    Timeout(std::time::Duration)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EngineExit {
+    /// `None` if the process could not be reaped, e.g. because it closed
+    /// its stdout without exiting.
+    pub status: Option<ExitStatus>,
+    /// The last lines the engine wrote to stderr.
+    pub stderr: Vec<String>
+}
+
+impl Display for EngineExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.status {
+            Some(status) => write!(f, "{}", status),
+            None => write!(f, "unknown exit status")
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use cozy_uci::UciFormatOptions;
 use cozy_uci::remark::{UciRemark, UciIdInfo, UciOptionInfo};
@@ -11,11 +12,17 @@ mod uci_convert;
 mod raw_engine;
 mod error;
 mod analysis;
+mod transcript;
 
 use uci_convert::*;
 pub use error::*;
 pub use raw_engine::*;
 pub use analysis::*;
+pub use transcript::*;
+pub use uci_convert::canonicalize_move;
+
+/// How long [`Engine::stop`] waits for the best move of a stopped search.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum UciOptionField {
@@ -49,16 +56,20 @@ pub struct Engine {
     engine: RawEngine,
     engine_name: String,
     engine_author: String,
-    options: BTreeMap<String, UciOptionField>
+    options: BTreeMap<String, UciOptionField>,
+    last_search: Option<SearchRecord>,
+    searching: bool
 }
 
 impl Engine {
-    pub async fn new(path: &Path, args: &[String]) -> Result<(Self, Vec<EngineError>), EngineError> {
+    pub async fn new(path: &Path, args: &[String], transcript: Option<Transcript>) -> Result<(Self, Vec<EngineError>), EngineError> {
         let mut this = Self {
-            engine: RawEngine::new(path, args).await?,
+            engine: RawEngine::new(path, args, transcript).await?,
             engine_name: String::new(),
             engine_author: String::new(),
-            options: BTreeMap::new()
+            options: BTreeMap::new(),
+            last_search: None,
+            searching: false
         };
         let errors = this.init().await?;
         Ok((this, errors))
@@ -70,7 +81,7 @@ impl Engine {
         let mut engine_author = None;
         self.send(&UciCommand::Uci).await?;
         loop {
-            match self.recv().await?.ok_or(EngineError::UnexpectedTermination)? {
+            match self.recv().await? {
                 UciRemark::UciOk => break,
                 UciRemark::Id(UciIdInfo::Name(name)) if engine_name.is_none() => {
                     engine_name = Some(name);
@@ -116,6 +127,61 @@ impl Engine {
         Ok(warnings)
     }
 
+    pub async fn new_game(&mut self) -> Result<(), EngineError> {
+        self.last_search = None;
+        self.send(&UciCommand::UciNewGame).await?;
+        self.send(&UciCommand::IsReady).await?;
+        loop {
+            // Stray remarks from a previous search may still be in flight.
+            if let UciRemark::ReadyOk = self.recv().await? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops the running search, if any, and waits up to [`STOP_TIMEOUT`]
+    /// for its best move.
+    pub async fn stop(&mut self) -> Result<(), EngineError> {
+        if !self.searching {
+            return Ok(());
+        }
+        self.send(&UciCommand::Stop).await?;
+        let best_move = async {
+            loop {
+                if let UciRemark::BestMove { .. } = self.recv().await? {
+                    return Ok::<_, EngineError>(());
+                }
+            }
+        };
+        tokio::time::timeout(STOP_TIMEOUT, best_move).await
+            .map_err(|_| EngineError::Timeout(STOP_TIMEOUT))??;
+        self.searching = false;
+        Ok(())
+    }
+
+    /// Sends `isready` and returns how long the engine took to answer.
+    pub async fn ping(&mut self) -> Result<Duration, EngineError> {
+        let start = Instant::now();
+        self.send(&UciCommand::IsReady).await?;
+        loop {
+            if let UciRemark::ReadyOk = self.recv().await? {
+                break;
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    /// The median round trip time of `pings` pings.
+    pub async fn measure_latency(&mut self, pings: u32) -> Result<Duration, EngineError> {
+        let mut round_trips = Vec::new();
+        for _ in 0..pings.max(1) {
+            round_trips.push(self.ping().await?);
+        }
+        round_trips.sort();
+        Ok(round_trips[round_trips.len() / 2])
+    }
+
     pub fn options(&self) -> &BTreeMap<String, UciOptionField> {
         &self.options
     }
@@ -152,6 +218,19 @@ impl Engine {
         Ok(())
     }
 
+    /// The most recent search of the current game, if the game has had one.
+    pub fn last_search(&self) -> Option<&SearchRecord> {
+        self.last_search.as_ref()
+    }
+
+    pub fn transcript(&self) -> Option<&Transcript> {
+        self.engine.transcript()
+    }
+
+    pub fn recent_stderr(&self) -> Vec<String> {
+        self.engine.recent_stderr()
+    }
+
     pub fn chess960_supported(&self) -> bool {
         matches!(self.options.get("UCI_Chess960"), Some(&UciOptionField::Check { .. }))
     }
@@ -171,7 +250,7 @@ impl Engine {
         self.engine.send(cmd, &self.uci_format_opts()).await
     }
 
-    async fn recv(&mut self) -> Result<Option<UciRemark>, EngineError> {
+    async fn recv(&mut self) -> Result<UciRemark, EngineError> {
         self.engine.recv(&self.uci_format_opts()).await
     }
 
@@ -183,15 +262,33 @@ impl Engine {
         let board = game.board().clone();
         let position_cmd = game_to_position_message(game, chess960);
         let go_cmd = analysis_limit_to_go_message(limit);
+        let fmt_opts = self.uci_format_opts();
+        self.last_search = Some(SearchRecord {
+            board: board.clone(),
+            limit,
+            position: position_cmd.format(&fmt_opts),
+            go: go_cmd.format(&fmt_opts)
+        });
+        // Stderr written before the search belongs to something else.
+        self.engine.clear_stderr();
         let stream = Box::pin(async_stream::try_stream! {
             self.send(&position_cmd).await?;
             self.send(&go_cmd).await?;
+            self.searching = true;
             loop {
-                match self.recv().await?.ok_or(EngineError::UnexpectedTermination)? {
+                let rmk = match self.engine.recv_output(&fmt_opts).await? {
+                    EngineOutput::Remark(rmk) => rmk,
+                    EngineOutput::Stderr(line) => {
+                        yield EngineAnalysisEvent::Stderr(line);
+                        continue;
+                    }
+                };
+                match rmk {
                     UciRemark::Info(info) => {
                         yield EngineAnalysisEvent::Info(info);
                     }
                     UciRemark::BestMove { mv, .. } => {
+                        self.searching = false;
                         let mv = canonicalize_move(&board, mv, false);
                         yield EngineAnalysisEvent::BestMove(mv);
                         break;
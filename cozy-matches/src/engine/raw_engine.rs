@@ -1,24 +1,44 @@
+use std::collections::VecDeque;
 use std::path::Path;
 use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use cozy_uci::command::UciCommand;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
 use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use cozy_uci::UciFormatOptions;
 use cozy_uci::remark::UciRemark;
 
-use super::error::EngineError;
+use super::error::{EngineError, EngineExit};
+use super::transcript::{Transcript, TranscriptDirection};
+
+const RECENT_STDERR_LINES: usize = 20;
+const PENDING_STDERR_LINES: usize = 1024;
+
+/// A line read from either of the engine's output pipes.
+#[derive(Debug)]
+pub enum EngineOutput {
+    Remark(UciRemark),
+    Stderr(String)
+}
 
 #[derive(Debug)]
 pub struct RawEngine {
-    _child: Child,
+    child: Child,
     stdin: ChildStdin,
-    stdout: BufReader<ChildStdout>,
-    pub stderr: Option<BufReader<ChildStderr>>,
+    stdout: Lines<BufReader<ChildStdout>>,
+    /// Stderr lines not yet taken by [`RawEngine::recv_output`].
+    stderr: Option<mpsc::Receiver<String>>,
+    recent_stderr: Arc<Mutex<VecDeque<String>>>,
+    stderr_task: Option<JoinHandle<()>>,
+    transcript: Option<Transcript>,
 }
 
 impl RawEngine {
-    pub async fn new(path: &Path, args: &[String]) -> tokio::io::Result<Self> {
+    pub async fn new(path: &Path, args: &[String], transcript: Option<Transcript>) -> tokio::io::Result<Self> {
         let mut child = Command::new(path)
             .kill_on_drop(true)
             .stdin(Stdio::piped())
@@ -27,29 +47,149 @@ impl RawEngine {
             .args(args)
             .spawn()?;
         let stdin = child.stdin.take().unwrap();
-        let stdout = BufReader::new(child.stdout.take().unwrap());
-        let stderr = Some(BufReader::new(child.stderr.take().unwrap()));
+        let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        // Stderr is always drained so a chatty engine can't block on a full pipe.
+        let (sender, stderr) = mpsc::channel(PENDING_STDERR_LINES);
+        let recent_stderr = Arc::new(Mutex::new(VecDeque::new()));
+        let stderr_task = tokio::spawn(drain_stderr(
+            child.stderr.take().unwrap(),
+            sender,
+            recent_stderr.clone(),
+            transcript.clone()
+        ));
 
         Ok(Self {
-            _child: child,
+            child,
             stdin,
             stdout,
-            stderr,
+            stderr: Some(stderr),
+            recent_stderr,
+            stderr_task: Some(stderr_task),
+            transcript,
         })
     }
 
     pub async fn send(&mut self, cmd: &UciCommand, options: &UciFormatOptions) -> Result<(), EngineError> {
-        self.stdin.write_all(cmd.format(options).as_bytes()).await?;
-        Ok(())
+        self.send_line(&cmd.format(options)).await
     }
 
-    pub async fn recv(&mut self, options: &UciFormatOptions) -> Result<Option<UciRemark>, EngineError> {
-        let mut rmk = String::new();
-        if self.stdout.read_line(&mut rmk).await? == 0 {
-            return Ok(None);
-        }
+    pub async fn recv(&mut self, options: &UciFormatOptions) -> Result<UciRemark, EngineError> {
+        let rmk = self.recv_line().await?;
         let rmk = UciRemark::parse_from(&rmk, options)
             .map_err(|e| EngineError::InvalidMessage(rmk, e))?;
-        Ok(Some(rmk))
+        Ok(rmk)
+    }
+
+    /// Waits for either a remark on stdout or a line on stderr, whichever
+    /// comes first, so stderr is seen even if the engine hangs.
+    pub async fn recv_output(&mut self, options: &UciFormatOptions) -> Result<EngineOutput, EngineError> {
+        loop {
+            let stderr = async {
+                match &mut self.stderr {
+                    Some(stderr) => stderr.recv().await,
+                    None => std::future::pending().await
+                }
+            };
+            // Both branches are cancellation safe, so no line is lost to the other.
+            tokio::select! {
+                line = self.stdout.next_line() => {
+                    let line = line?;
+                    let rmk = self.received(line).await?;
+                    let rmk = UciRemark::parse_from(&rmk, options)
+                        .map_err(|e| EngineError::InvalidMessage(rmk, e))?;
+                    return Ok(EngineOutput::Remark(rmk));
+                }
+                line = stderr => match line {
+                    Some(line) => return Ok(EngineOutput::Stderr(line)),
+                    None => self.stderr = None
+                }
+            }
+        }
+    }
+
+    /// Sends a raw line to the engine. The line must be newline terminated.
+    pub async fn send_line(&mut self, line: &str) -> Result<(), EngineError> {
+        self.log(TranscriptDirection::Sent, line);
+        match self.stdin.write_all(line.as_bytes()).await {
+            Err(e) if e.kind() == tokio::io::ErrorKind::BrokenPipe => Err(self.termination().await),
+            result => Ok(result?)
+        }
+    }
+
+    /// Reads a line from stdout, without the line terminator.
+    pub async fn recv_line(&mut self) -> Result<String, EngineError> {
+        let line = self.stdout.next_line().await?;
+        self.received(line).await
+    }
+
+    async fn received(&mut self, line: Option<String>) -> Result<String, EngineError> {
+        let line = match line {
+            Some(line) => line,
+            None => return Err(self.termination().await)
+        };
+        self.log(TranscriptDirection::Received, &line);
+        Ok(line)
+    }
+
+    /// Discards the stderr lines not yet taken by [`RawEngine::recv_output`].
+    pub fn clear_stderr(&mut self) {
+        if let Some(stderr) = &mut self.stderr {
+            while stderr.try_recv().is_ok() {}
+        }
+    }
+
+    pub fn recent_stderr(&self) -> Vec<String> {
+        self.recent_stderr.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn transcript(&self) -> Option<&Transcript> {
+        self.transcript.as_ref()
+    }
+
+    fn log(&self, direction: TranscriptDirection, line: &str) {
+        if let Some(transcript) = &self.transcript {
+            transcript.log(direction, line);
+        }
+    }
+
+    async fn termination(&mut self) -> EngineError {
+        // The pipes close slightly before the process can be reaped.
+        let timeout = Duration::from_secs(1);
+        let status = tokio::time::timeout(timeout, self.child.wait()).await
+            .ok()
+            .and_then(Result::ok);
+        if let Some(stderr_task) = self.stderr_task.take() {
+            let _ = tokio::time::timeout(timeout, stderr_task).await;
+        }
+        let stderr = self.recent_stderr();
+        EngineError::UnexpectedTermination(EngineExit { status, stderr })
+    }
+}
+
+/// Lines that don't fit in the channel are dropped rather than blocking
+/// the engine, but are still logged and kept as recent stderr.
+async fn drain_stderr(
+    pipe: ChildStderr,
+    sender: mpsc::Sender<String>,
+    recent: Arc<Mutex<VecDeque<String>>>,
+    transcript: Option<Transcript>
+) {
+    let mut pipe = BufReader::new(pipe);
+    let mut line = Vec::new();
+    while let Ok(1..) = pipe.read_until(b'\n', &mut line).await {
+        let text = String::from_utf8_lossy(&line);
+        if let Some(transcript) = &transcript {
+            transcript.log(TranscriptDirection::Stderr, &text);
+        }
+        let text = text.trim_end().to_owned();
+        {
+            let mut recent = recent.lock().unwrap();
+            if recent.len() == RECENT_STDERR_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(text.clone());
+        }
+        let _ = sender.try_send(text);
+        line.clear();
     }
 }
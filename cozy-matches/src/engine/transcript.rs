@@ -0,0 +1,155 @@
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptDirection {
+    Sent,
+    Received,
+    Stderr
+}
+
+impl TranscriptDirection {
+    pub fn symbol(self) -> char {
+        match self {
+            Self::Sent => '>',
+            Self::Received => '<',
+            Self::Stderr => '!'
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            ">" => Some(Self::Sent),
+            "<" => Some(Self::Received),
+            "!" => Some(Self::Stderr),
+            _ => None
+        }
+    }
+}
+
+/// A single parsed line of a [`Transcript`].
+#[derive(Debug, Clone)]
+pub struct TranscriptEntry {
+    pub time: Duration,
+    pub engine: String,
+    pub game: Option<u64>,
+    pub direction: TranscriptDirection,
+    pub line: String
+}
+
+#[derive(Debug, Error, Clone, Copy)]
+#[error("invalid transcript entry")]
+pub struct InvalidTranscriptEntry;
+
+impl FromStr for TranscriptEntry {
+    type Err = InvalidTranscriptEntry;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (time, rest) = s.split_once(' ').ok_or(InvalidTranscriptEntry)?;
+        let time: f64 = time.parse().map_err(|_| InvalidTranscriptEntry)?;
+        let time = Duration::try_from_secs_f64(time).map_err(|_| InvalidTranscriptEntry)?;
+        let (header, rest) = rest.strip_prefix('[')
+            .and_then(|rest| rest.split_once("] "))
+            .ok_or(InvalidTranscriptEntry)?;
+        let (engine, game) = header.rsplit_once('#').ok_or(InvalidTranscriptEntry)?;
+        let game = match game {
+            "-" => None,
+            game => Some(game.parse().map_err(|_| InvalidTranscriptEntry)?)
+        };
+        let (direction, line) = rest.split_once(' ').unwrap_or((rest, ""));
+        let direction = TranscriptDirection::from_symbol(direction).ok_or(InvalidTranscriptEntry)?;
+        Ok(Self {
+            time,
+            engine: engine.to_owned(),
+            game,
+            direction,
+            line: line.to_owned()
+        })
+    }
+}
+
+#[derive(Debug)]
+enum TranscriptSink {
+    File(LineWriter<File>),
+    Memory(Vec<String>)
+}
+
+#[derive(Debug)]
+struct TranscriptState {
+    game: Option<u64>,
+    sink: TranscriptSink
+}
+
+/// A log of every line exchanged with an engine, in the form
+/// `<unix time> [<engine>#<game>] <direction> <line>`. Wall clock time keeps
+/// the timestamps of a log appended to by several engine processes in order.
+/// Clones share the same underlying sink.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    engine: String,
+    state: Arc<Mutex<TranscriptState>>
+}
+
+impl Transcript {
+    /// Appends to the file at `path`, so restarted engines keep a single log.
+    pub fn to_file(engine: String, path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(engine, TranscriptSink::File(LineWriter::new(file))))
+    }
+
+    pub fn in_memory(engine: String) -> Self {
+        Self::new(engine, TranscriptSink::Memory(Vec::new()))
+    }
+
+    fn new(engine: String, sink: TranscriptSink) -> Self {
+        let state = TranscriptState {
+            game: None,
+            sink
+        };
+        Self {
+            engine,
+            state: Arc::new(Mutex::new(state))
+        }
+    }
+
+    pub fn set_game(&self, game: Option<u64>) {
+        self.state.lock().unwrap().game = game;
+    }
+
+    /// The lines logged so far, if this is an in-memory transcript.
+    pub fn lines(&self) -> Option<Vec<String>> {
+        match &self.state.lock().unwrap().sink {
+            TranscriptSink::File(_) => None,
+            TranscriptSink::Memory(lines) => Some(lines.clone())
+        }
+    }
+
+    pub fn log(&self, direction: TranscriptDirection, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        let game = match state.game {
+            Some(game) => game.to_string(),
+            None => "-".to_owned()
+        };
+        let entry = format!(
+            "{:.3} [{}#{}] {} {}",
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            self.engine,
+            game,
+            direction.symbol(),
+            line.trim_end()
+        );
+        match &mut state.sink {
+            // A broken debug log shouldn't take the engine down with it.
+            TranscriptSink::File(file) => {
+                let _ = writeln!(file, "{}", entry);
+            }
+            TranscriptSink::Memory(lines) => lines.push(entry)
+        }
+    }
+}
@@ -1,17 +1,30 @@
 use std::time::{Duration, Instant};
 
 use cozy_chess::*;
+use cozy_uci::remark::UciInfo;
 use tokio_stream::{StreamExt, Stream};
 use thiserror::Error;
 
 use crate::time_control::TimeControl;
-use crate::engine::{Engine, EngineAnalysisEvent, AnalysisSearchLimit, AnalysisLimit, AnalysisTimeLimit, EngineError};
+use crate::clock::ChessClock;
+use crate::time_usage::TimeUsage;
+use crate::adjudication::{Adjudicator, AdjudicationContext};
+use crate::engine::{Engine, EngineAnalysisEvent, AnalysisSearchLimit, AnalysisLimit, EngineError, EngineExit};
 use crate::game::ChessGame;
+use crate::player::Player;
 
 #[derive(Debug, Clone)]
 pub struct EngineMatchConfig {
     pub white_time_control: EngineMatchTimeConfig,
-    pub black_time_control: EngineMatchTimeConfig
+    pub black_time_control: EngineMatchTimeConfig,
+    /// How far an engine may overrun its time before it loses on time.
+    pub time_margin: Duration,
+    /// `isready` pings to measure each engine's latency with before the game.
+    pub latency_pings: u32,
+    /// Don't charge engines for their measured latency.
+    pub compensate_latency: bool,
+    /// Consulted in order after each move until one ends the game.
+    pub adjudicators: Vec<Box<dyn Adjudicator>>
 }
 
 #[derive(Debug, Clone)]
@@ -24,45 +37,104 @@ pub struct EngineMatchTimeConfig {
 pub enum ChessClockState {
     Infinite,
     MoveTime(Duration),
-    Clock(TimeControl)
+    Clock(TimeControl),
+    /// A clock charged for the nodes the engine reports searching instead of
+    /// the wall time it took, at `nps` nodes per second of the time control.
+    NodesAsTime {
+        time_control: TimeControl,
+        nps: u64
+    }
 }
 
 impl ChessClockState {
-    pub fn update(&mut self, elapsed: Duration) -> bool {
+    pub fn time_control(&self) -> Option<&TimeControl> {
         match self {
-            ChessClockState::Infinite => false,
-            ChessClockState::MoveTime(move_time) => elapsed > *move_time,
-            ChessClockState::Clock(TimeControl { time, increment }) => {
-                *time = (*time + *increment).saturating_sub(elapsed);
-                time.is_zero()
-            }
+            Self::Clock(time_control) | Self::NodesAsTime { time_control, .. } => Some(time_control),
+            _ => None
         }
     }
 
-    pub fn as_tc(&self) -> Option<&TimeControl> {
-        if let Self::Clock(tc) = self {
-            Some(tc)
-        } else {
-            None
+    /// The time to charge for a search that took `elapsed` and searched
+    /// `nodes`. Falls back to `elapsed` if the engine reported no nodes.
+    pub fn charged_time(&self, elapsed: Duration, nodes: Option<u64>) -> Duration {
+        match (self, nodes) {
+            (Self::NodesAsTime { nps, .. }, Some(nodes)) => Duration::from_secs_f64(nodes as f64 / (*nps).max(1) as f64),
+            _ => elapsed
         }
     }
 }
 
 #[derive(Debug)]
-pub struct EngineMatch {
+pub struct EngineMatch<'e, P: Player + ?Sized = Engine> {
     config: EngineMatchConfig,
     game: ChessGame,
-    engines: [Engine; Color::NUM],
+    engines: [&'e mut P; Color::NUM],
 }
 
 #[derive(Debug)]
 pub enum EngineMatchEvent {
+    /// The round trip time measured before the game.
+    LatencyMeasured {
+        color: Color,
+        latency: Duration
+    },
+    /// Sent right before an engine is asked to search.
+    SearchStarted {
+        color: Color,
+        limit: AnalysisLimit
+    },
     EngineAnalysisEvent {
         engine: Color,
         event: EngineAnalysisEvent
     },
+    /// Sent after a legal move is played, with the clocks after the move.
+    MovePlayed {
+        color: Color,
+        mv: Move,
+        elapsed: Duration,
+        white_clock: Option<Duration>,
+        black_clock: Option<Duration>
+    },
     GameOver {
-        winner: Option<Color>
+        winner: Option<Color>,
+        termination: GameTermination,
+        time_usage: [TimeUsage; Color::NUM]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum GameTermination {
+    Normal,
+    TimeForfeit,
+    Crash {
+        exit: EngineExit
+    },
+    IllegalMove {
+        mv: Move
+    },
+    /// The engine misbehaved in a way that broke the protocol, such as
+    /// sending a line that couldn't be parsed.
+    EngineError {
+        error: String
+    },
+    Adjudication,
+    Resignation,
+    Tablebase,
+    MaxMoves
+}
+
+impl GameTermination {
+    /// Whether the game ended because the losing engine misbehaved.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Self::TimeForfeit | Self::Crash { .. } | Self::IllegalMove { .. } | Self::EngineError { .. })
+    }
+
+    /// The termination of a game lost because an engine failed with `error`.
+    fn engine_failure(error: EngineError) -> Self {
+        match error {
+            EngineError::UnexpectedTermination(exit) => Self::Crash { exit },
+            error => Self::EngineError { error: error.to_string() }
+        }
     }
 }
 
@@ -74,13 +146,13 @@ pub enum EngineMatchInitError {
 
 #[derive(Debug, Error)]
 pub enum EngineMatchError {
-    #[error("engine error")]
+    #[error("engine error: {0}")]
     EngineError(#[from] EngineError)
 }
 
-impl EngineMatch {
-    pub fn new(config: EngineMatchConfig, game: ChessGame, white: Engine, black: Engine) -> Result<Self, EngineMatchInitError> {
-        if game.needs_chess960() && !(white.chess960_enabled() && black.chess960_enabled()) {
+impl<'e, P: Player + ?Sized> EngineMatch<'e, P> {
+    pub fn new(config: EngineMatchConfig, game: ChessGame, white: &'e mut P, black: &'e mut P) -> Result<Self, EngineMatchInitError> {
+        if game.needs_chess960() && !(white.chess960() && black.chess960()) {
             Err(EngineMatchInitError::Requires960)?;
         }
 
@@ -91,73 +163,173 @@ impl EngineMatch {
         })
     }
 
-    pub fn run(mut self) -> impl Stream<Item = Result<EngineMatchEvent, EngineMatchError>> {
+    pub fn run(mut self) -> impl Stream<Item = Result<EngineMatchEvent, EngineMatchError>> + 'e {
         async_stream::try_stream! {
-            let mut white_clock = self.config.white_time_control.clock.clone();
-            let mut black_clock = self.config.black_time_control.clock.clone();
+            let mut clock = ChessClock::new(
+                self.config.white_time_control.clock.clone(),
+                self.config.black_time_control.clock.clone(),
+                self.config.time_margin
+            );
             
             let mut match_result = match self.game.status() {
                 GameStatus::Won => Some(Some(!self.game.board().side_to_move())),
                 GameStatus::Drawn => Some(None),
                 GameStatus::Ongoing => None,
-            };
+            }.map(|winner| (winner, GameTermination::Normal));
+            let mut infos: [Option<UciInfo>; Color::NUM] = [None, None];
+            let mut time_usage: [TimeUsage; Color::NUM] = Default::default();
+            let mut overhead = [Duration::ZERO; Color::NUM];
+            for color in Color::ALL {
+                if match_result.is_some() {
+                    break;
+                }
+                if let Err(e) = self.engines[color as usize].new_game().await {
+                    match_result = Some((Some(!color), GameTermination::engine_failure(e)));
+                }
+            }
+            if self.config.latency_pings > 0 {
+                for color in Color::ALL {
+                    if match_result.is_some() {
+                        break;
+                    }
+                    match self.engines[color as usize].measure_latency(self.config.latency_pings).await {
+                        Ok(latency) => {
+                            if self.config.compensate_latency {
+                                overhead[color as usize] = latency;
+                            }
+                            yield EngineMatchEvent::LatencyMeasured { color, latency };
+                        }
+                        Err(e) => match_result = Some((Some(!color), GameTermination::engine_failure(e)))
+                    }
+                }
+            }
             while match_result.is_none() {
                 let stm = self.game.board().side_to_move();
 
-                let white_tc = white_clock.as_tc();
-                let black_tc = black_clock.as_tc();
-                let clock = match stm {
-                    Color::White => &white_clock,
-                    Color::Black => &black_clock,
-                };
-                let time_limit = match clock {
-                    ChessClockState::Infinite => AnalysisTimeLimit::Infinite,
-                    ChessClockState::MoveTime(move_time) => AnalysisTimeLimit::MoveTime(*move_time),
-                    ChessClockState::Clock(_) => AnalysisTimeLimit::TimeLeft {
-                        white_time: white_tc.map(|c| c.time),
-                        black_time: black_tc.map(|c| c.time),
-                        white_increment: white_tc.map(|c| c.increment),
-                        black_increment: black_tc.map(|c| c.increment),
-                        moves_to_go: None
-                    }
-                };
                 let search_limit = match stm {
                     Color::White => self.config.white_time_control.search_limit,
                     Color::Black => self.config.black_time_control.search_limit
                 };
+                // `go infinite` would keep the engine from stopping at its search limit.
+                let time_limit = match (clock.state(stm), search_limit) {
+                    (ChessClockState::Infinite, Some(_)) => None,
+                    _ => Some(clock.time_limit(stm))
+                };
                 let limit = AnalysisLimit {
                     search_limit,
-                    time_limit: Some(time_limit),
+                    time_limit,
                 };
 
+                yield EngineMatchEvent::SearchStarted { color: stm, limit };
                 let analyis_start = Instant::now();
                 let mut analysis = self.engines[stm as usize].analyze(&self.game, limit).unwrap();
                 let mut best_move = None;
+                let mut nodes = None;
+                infos[stm as usize] = None;
+                let mut failure = None;
+                let mut resigned = false;
+                let mut takeback = false;
                 while let Some(event) = analysis.next().await {
-                    let event = event?;
-                    if let EngineAnalysisEvent::BestMove(mv) = event {
-                        best_move = Some(mv);
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            failure = Some(e);
+                            break;
+                        }
+                    };
+                    match &event {
+                        EngineAnalysisEvent::Info(info) => {
+                            nodes = info.nodes.or(nodes);
+                            if info.score.is_some() {
+                                infos[stm as usize] = Some(info.clone());
+                            }
+                        }
+                        EngineAnalysisEvent::BestMove(mv) => best_move = Some(*mv),
+                        EngineAnalysisEvent::Resign => resigned = true,
+                        EngineAnalysisEvent::Takeback => takeback = true,
+                        _ => {}
                     }
                     yield EngineMatchEvent::EngineAnalysisEvent { engine: stm, event };
                 }
-                let elapsed = analyis_start.elapsed();
-                let timed_out = match stm {
-                    Color::White => white_clock.update(elapsed),
-                    Color::Black => black_clock.update(elapsed),
-                };
+                drop(analysis);
+                // A player that failed may never answer `stop`, so only one that
+                // abandoned its search cleanly is stopped.
+                if failure.is_none() && best_move.is_none() && !resigned && !takeback {
+                    let _ = self.engines[stm as usize].stop().await;
+                    match_result = Some((Some(!stm), GameTermination::EngineError {
+                        error: "search ended without a best move".to_owned()
+                    }));
+                    break;
+                }
+                if let Some(e) = failure {
+                    match_result = Some((Some(!stm), GameTermination::engine_failure(e)));
+                    break;
+                }
+                if resigned {
+                    match_result = Some((Some(!stm), GameTermination::Resignation));
+                    break;
+                }
+                // Clocks aren't rewound, and the time spent before taking back isn't charged.
+                if takeback {
+                    if self.game.stack().len() < 2 {
+                        match_result = Some((Some(!stm), GameTermination::EngineError {
+                            error: "took back a move that wasn't played".to_owned()
+                        }));
+                        break;
+                    }
+                    self.game.undo();
+                    self.game.undo();
+                    infos = [None, None];
+                    continue;
+                }
+                let elapsed = analyis_start.elapsed().saturating_sub(overhead[stm as usize]);
+                let charged = clock.state(stm).charged_time(elapsed, nodes);
+                let before = clock.time_left(stm);
+                let update = clock.update(stm, charged);
+                let timed_out = update.flagged;
+                time_usage[stm as usize].record(
+                    clock.state(stm),
+                    clock.moves(stm),
+                    elapsed,
+                    charged,
+                    before,
+                    update.time_left
+                );
                 let best_move = best_move.unwrap();
+                if !self.game.board().is_legal(best_move) {
+                    match_result = Some((Some(!stm), GameTermination::IllegalMove { mv: best_move }));
+                    break;
+                }
 
                 self.game.play(best_move);
+                yield EngineMatchEvent::MovePlayed {
+                    color: stm,
+                    mv: best_move,
+                    elapsed,
+                    white_clock: clock.time_left(Color::White),
+                    black_clock: clock.time_left(Color::Black)
+                };
                 match_result = match self.game.status() {
-                    GameStatus::Won => Some(Some(stm)),
-                    GameStatus::Drawn => Some(None),
-                    GameStatus::Ongoing if timed_out => Some(Some(!stm)),
+                    GameStatus::Won => Some((Some(stm), GameTermination::Normal)),
+                    GameStatus::Drawn => Some((None, GameTermination::Normal)),
+                    GameStatus::Ongoing if timed_out => Some((Some(!stm), GameTermination::TimeForfeit)),
                     GameStatus::Ongoing => None,
+                };
+                for adjudicator in &mut self.config.adjudicators {
+                    if match_result.is_some() {
+                        break;
+                    }
+                    let context = AdjudicationContext {
+                        game: &self.game,
+                        clock: &clock,
+                        infos: [infos[0].as_ref(), infos[1].as_ref()]
+                    };
+                    match_result = adjudicator.adjudicate(&context);
                 }
             }
-            let winner = match_result.unwrap();
+            let (winner, termination) = match_result.unwrap();
             
-            yield EngineMatchEvent::GameOver { winner };
+            yield EngineMatchEvent::GameOver { winner, termination, time_usage };
         }
     }
 }
@@ -56,4 +56,9 @@ impl ChessGame {
         child.play(mv);
         self.stack.push((mv, child));
     }
+
+    /// Takes back the last move, returning it.
+    pub fn undo(&mut self) -> Option<Move> {
+        self.stack.pop().map(|(mv, _)| mv)
+    }
 }
@@ -1,4 +1,11 @@
 pub mod game;
 pub mod engine;
+pub mod player;
 pub mod engine_match;
 pub mod time_control;
+pub mod clock;
+pub mod time_usage;
+pub mod adjudication;
+pub mod lint;
+#[cfg(feature = "syzygy")]
+pub mod tablebase;
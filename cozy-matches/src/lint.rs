@@ -0,0 +1,368 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use cozy_chess::*;
+use tokio_stream::StreamExt;
+
+use crate::engine::*;
+use crate::game::ChessGame;
+
+/// Black to move with a white pawn on e5, so d7d5 can be taken en passant.
+const EN_PASSANT_FEN: &str = "rnbqkbnr/pppppppp/8/4P3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+const EN_PASSANT_MOVES: &[&str] = &["d7d5", "e5d6", "c7d6"];
+/// Both sides can castle either way, with the king and rooks away from their usual files.
+const CHESS960_FEN: &str = "r2k3r/pppppppp/8/8/8/8/PPPPPPPP/R2K3R w HAha - 0 1";
+const CHESS960_MOVES: &[&str] = &["d1h1", "d8a8"];
+/// How long to let an engine search before interrupting it.
+const SEARCH_TIME: Duration = Duration::from_millis(200);
+
+pub struct LintOptions {
+    /// How long to wait for any response before reporting a hang.
+    pub timeout: Duration,
+    /// How long an engine may take to send its best move after `stop`.
+    pub stop_timeout: Duration
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScenarioReport {
+    pub name: &'static str,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub skipped: Option<String>,
+    pub violations: Vec<String>
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LintReport {
+    pub engine: String,
+    pub scenarios: Vec<ScenarioReport>
+}
+
+impl LintReport {
+    pub fn violations(&self) -> usize {
+        self.scenarios.iter().map(|scenario| scenario.violations.len()).sum()
+    }
+}
+
+impl Display for LintReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for scenario in &self.scenarios {
+            match &scenario.skipped {
+                Some(reason) => writeln!(f, "{}: skipped ({})", scenario.name, reason)?,
+                None if scenario.violations.is_empty() => writeln!(f, "{}: ok", scenario.name)?,
+                None => writeln!(f, "{}: {} violations", scenario.name, scenario.violations.len())?
+            }
+            for violation in &scenario.violations {
+                writeln!(f, "    {}", violation)?;
+            }
+        }
+        write!(f, "{}: {} violations", self.engine, self.violations())
+    }
+}
+
+/// Why a scenario ended early.
+enum Abort {
+    Violation(String),
+    Skipped(String)
+}
+
+fn describe(e: &EngineError) -> String {
+    match e {
+        EngineError::InvalidMessage(line, _) => format!("invalid message {:?}", line.trim()),
+        EngineError::UnexpectedRemark(rmk) => format!("unexpected remark {:?}", rmk),
+        EngineError::UnexpectedTermination(exit) => match exit.stderr.last() {
+            Some(line) => format!("{} ({})", e, line),
+            None => e.to_string()
+        },
+        e => e.to_string()
+    }
+}
+
+async fn timed<T>(limit: Duration, what: &str, future: impl Future<Output = Result<T, EngineError>>) -> Result<T, Abort> {
+    match tokio::time::timeout(limit, future).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(Abort::Violation(format!("{}: {}", what, describe(&e)))),
+        Err(_) => Err(Abort::Violation(format!("{}: no response after {:.3}s", what, limit.as_secs_f64())))
+    }
+}
+
+fn game_from(fen: &str, shredder: bool, moves: &[&str]) -> ChessGame {
+    let mut game = ChessGame::new(Board::from_fen(fen, shredder).unwrap());
+    for mv in moves {
+        game.play(mv.parse().unwrap());
+    }
+    game
+}
+
+/// The state of the scenario being run. Every scenario starts its own
+/// engine, so one that leaves the engine in a bad state can't affect the next.
+struct Scenario<'a> {
+    path: &'a Path,
+    args: &'a [String],
+    options: &'a LintOptions,
+    violations: Vec<String>
+}
+
+impl Scenario<'_> {
+    async fn spawn(&self) -> Result<(Engine, Vec<EngineError>), Abort> {
+        let limit = self.options.timeout;
+        timed(limit, "handshake", Engine::new(self.path, self.args, None)).await
+    }
+
+    async fn spawn_raw(&self) -> Result<RawEngine, Abort> {
+        let mut engine = RawEngine::new(self.path, self.args, None).await
+            .map_err(|e| Abort::Violation(format!("spawn: {}", e)))?;
+        timed(self.options.timeout, "uci", engine.send_line("uci\n")).await?;
+        self.wait_for(&mut engine, "uciok", self.options.timeout).await?;
+        Ok(engine)
+    }
+
+    /// Reads lines until one starts with `token`, returning the first words
+    /// of the lines before it.
+    async fn wait_for(&self, engine: &mut RawEngine, token: &str, limit: Duration) -> Result<Vec<String>, Abort> {
+        timed(limit, token, async {
+            let mut seen = Vec::new();
+            loop {
+                let line = engine.recv_line().await?;
+                let first = line.split_whitespace().next().unwrap_or_default();
+                if first == token {
+                    return Ok(seen);
+                }
+                seen.push(first.to_owned());
+            }
+        }).await
+    }
+
+    /// Searches `game` and checks that the best move is legal. Returns how
+    /// long the search took.
+    async fn search(&mut self, engine: &mut Engine, game: &ChessGame, limit: AnalysisLimit, what: &str) -> Result<Duration, Abort> {
+        let extra = match limit.time_limit {
+            Some(AnalysisTimeLimit::MoveTime(time)) => time,
+            Some(AnalysisTimeLimit::TimeLeft { white_time, black_time, .. }) => white_time.max(black_time).unwrap_or_default(),
+            _ => Duration::ZERO
+        };
+        let start = Instant::now();
+        let mut analysis = engine.analyze(game, limit)
+            .map_err(|e| Abort::Skipped(format!("{}: {}", what, e)))?;
+        let (best_move, errors) = timed(self.options.timeout + extra, what, async {
+            let mut best_move = None;
+            let mut errors = Vec::new();
+            while let Some(event) = analysis.next().await {
+                match event? {
+                    EngineAnalysisEvent::BestMove(mv) => best_move = Some(mv),
+                    EngineAnalysisEvent::EngineError(e) => errors.push(describe(&e)),
+                    _ => {}
+                }
+            }
+            Ok((best_move, errors))
+        }).await?;
+        let elapsed = start.elapsed();
+        for error in errors {
+            self.violations.push(format!("{}: {}", what, error));
+        }
+        if let Some(mv) = best_move {
+            if !game.board().is_legal(mv) {
+                self.violations.push(format!("{}: illegal bestmove {}", what, mv));
+            }
+        }
+        Ok(elapsed)
+    }
+}
+
+async fn handshake(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let (mut engine, warnings) = s.spawn().await?;
+    s.violations.extend(warnings.iter().map(describe));
+    timed(s.options.timeout, "isready", engine.ping()).await?;
+    Ok(())
+}
+
+/// Sets every advertised option to its bounds and checks the engine survives.
+async fn option_bounds(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let (mut engine, _) = s.spawn().await?;
+    let options = engine.options().clone();
+    for (name, field) in options {
+        let values = match field {
+            UciOptionField::Check { .. } => vec![
+                (UciOptionValue::Check(true), "true".to_owned()),
+                (UciOptionValue::Check(false), "false".to_owned())
+            ],
+            UciOptionField::Spin { min, max, .. } => vec![
+                (UciOptionValue::Spin(min), min.to_string()),
+                (UciOptionValue::Spin(max), max.to_string())
+            ],
+            UciOptionField::Combo { labels, .. } => labels.into_iter()
+                .enumerate()
+                .map(|(i, label)| (UciOptionValue::Combo(i), label))
+                .collect(),
+            UciOptionField::String { .. } => continue
+        };
+        for (value, label) in values {
+            let what = format!("setoption {} {}", name, label);
+            let result = timed(s.options.timeout, &what, async {
+                match engine.set_option(name.clone(), value).await {
+                    Err(SetOptionError::EngineError(e)) => Err(e),
+                    _ => engine.ping().await
+                }
+            }).await;
+            if let Err(Abort::Violation(violation)) = result {
+                s.violations.push(violation);
+                // The engine may have hung or crashed, so carry on with a new one.
+                engine = s.spawn().await?.0;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Searches with each kind of limit `go` supports.
+async fn go_limits(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let (mut engine, _) = s.spawn().await?;
+    let game = ChessGame::new(Board::default());
+    let search_limit = |nodes, depth| Some(AnalysisSearchLimit { nodes, depth });
+    let clock = |time, moves_to_go| AnalysisTimeLimit::TimeLeft {
+        white_time: Some(time),
+        black_time: Some(time),
+        white_increment: Some(time / 100),
+        black_increment: Some(time / 100),
+        moves_to_go
+    };
+    // Each limit with how long the search may take, if that is bounded.
+    let limits = [
+        ("go depth", search_limit(None, Some(2)), None, None),
+        ("go nodes", search_limit(Some(1000), None), None, None),
+        ("go movetime", None, Some(AnalysisTimeLimit::MoveTime(SEARCH_TIME)), Some(SEARCH_TIME + s.options.stop_timeout)),
+        ("go wtime", None, Some(clock(SEARCH_TIME * 20, None)), Some(SEARCH_TIME * 20)),
+        ("go movestogo", None, Some(clock(SEARCH_TIME * 5, Some(5))), Some(SEARCH_TIME * 5))
+    ];
+    for (what, search_limit, time_limit, allowed) in limits {
+        let limit = AnalysisLimit { search_limit, time_limit };
+        let elapsed = s.search(&mut engine, &game, limit, what).await?;
+        if matches!(allowed, Some(allowed) if elapsed > allowed) {
+            s.violations.push(format!("{}: searched for {:.3}s", what, elapsed.as_secs_f64()));
+        }
+    }
+    Ok(())
+}
+
+/// Sends a position with a starting FEN and moves, including en passant.
+async fn position_moves(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let (mut engine, _) = s.spawn().await?;
+    let limit = AnalysisLimit {
+        search_limit: None,
+        time_limit: Some(AnalysisTimeLimit::MoveTime(SEARCH_TIME))
+    };
+    for plies in 0..=EN_PASSANT_MOVES.len() {
+        let game = game_from(EN_PASSANT_FEN, false, &EN_PASSANT_MOVES[..plies]);
+        s.search(&mut engine, &game, limit, &format!("position fen after {} moves", plies)).await?;
+    }
+    Ok(())
+}
+
+/// Checks castling is sent and received as king takes rook in Chess960 mode.
+async fn chess960(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let (mut engine, _) = s.spawn().await?;
+    if !engine.chess960_supported() {
+        return Err(Abort::Skipped("UCI_Chess960 isn't advertised".to_owned()));
+    }
+    let what = "setoption UCI_Chess960";
+    timed(s.options.timeout, what, async {
+        match engine.set_option("UCI_Chess960".to_owned(), UciOptionValue::Check(true)).await {
+            Err(SetOptionError::EngineError(e)) => Err(e),
+            _ => engine.ping().await
+        }
+    }).await?;
+    let limit = AnalysisLimit {
+        search_limit: None,
+        time_limit: Some(AnalysisTimeLimit::MoveTime(SEARCH_TIME))
+    };
+    for plies in 0..=CHESS960_MOVES.len() {
+        let game = game_from(CHESS960_FEN, true, &CHESS960_MOVES[..plies]);
+        s.search(&mut engine, &game, limit, &format!("chess960 after {} moves", plies)).await?;
+    }
+    Ok(())
+}
+
+/// Sends `isready` during `go infinite`, which must not end the search.
+async fn isready_during_search(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let mut engine = s.spawn_raw().await?;
+    let timeout = s.options.timeout;
+    timed(timeout, "go infinite", engine.send_line("position startpos\ngo infinite\n")).await?;
+    tokio::time::sleep(SEARCH_TIME).await;
+    timed(timeout, "isready", engine.send_line("isready\n")).await?;
+    let seen = s.wait_for(&mut engine, "readyok", timeout).await?;
+    if seen.iter().any(|token| token == "bestmove") {
+        s.violations.push("isready: search ended before stop".to_owned());
+        return Ok(());
+    }
+    timed(timeout, "stop", engine.send_line("stop\n")).await?;
+    s.wait_for(&mut engine, "bestmove", timeout).await?;
+    Ok(())
+}
+
+/// Measures how long the engine takes to stop a `go infinite` search.
+async fn stop(s: &mut Scenario<'_>) -> Result<(), Abort> {
+    let mut engine = s.spawn_raw().await?;
+    let timeout = s.options.timeout;
+    timed(timeout, "go infinite", engine.send_line("position startpos\ngo infinite\n")).await?;
+    if s.wait_for(&mut engine, "bestmove", SEARCH_TIME).await.is_ok() {
+        s.violations.push("go infinite: bestmove before stop".to_owned());
+        return Ok(());
+    }
+    timed(timeout, "stop", engine.send_line("stop\n")).await?;
+    let start = Instant::now();
+    s.wait_for(&mut engine, "bestmove", timeout).await?;
+    let elapsed = start.elapsed();
+    if elapsed > s.options.stop_timeout {
+        s.violations.push(format!("stop: bestmove took {:.3}s", elapsed.as_secs_f64()));
+    }
+    Ok(())
+}
+
+fn finish(name: &'static str, s: Scenario<'_>, result: Result<(), Abort>) -> ScenarioReport {
+    let mut violations = s.violations;
+    let skipped = match result {
+        Ok(()) => None,
+        Err(Abort::Violation(violation)) => {
+            violations.push(violation);
+            None
+        }
+        Err(Abort::Skipped(reason)) => Some(reason)
+    };
+    ScenarioReport { name, skipped, violations }
+}
+
+/// Runs the engine at `path` through every scenario.
+pub async fn run(name: &str, path: &Path, args: &[String], options: &LintOptions) -> LintReport {
+    let mut scenarios = Vec::new();
+    let new = || Scenario { path, args, options, violations: Vec::new() };
+
+    let mut s = new();
+    let result = handshake(&mut s).await;
+    scenarios.push(finish("handshake", s, result));
+    let mut s = new();
+    let result = option_bounds(&mut s).await;
+    scenarios.push(finish("options", s, result));
+    let mut s = new();
+    let result = go_limits(&mut s).await;
+    scenarios.push(finish("go limits", s, result));
+    let mut s = new();
+    let result = position_moves(&mut s).await;
+    scenarios.push(finish("position with moves", s, result));
+    let mut s = new();
+    let result = chess960(&mut s).await;
+    scenarios.push(finish("chess960 castling", s, result));
+    let mut s = new();
+    let result = isready_during_search(&mut s).await;
+    scenarios.push(finish("isready during search", s, result));
+    let mut s = new();
+    let result = stop(&mut s).await;
+    scenarios.push(finish("stop", s, result));
+
+    LintReport {
+        engine: name.to_owned(),
+        scenarios
+    }
+}
@@ -0,0 +1,373 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use cozy_chess::*;
+use cozy_uci::UciFormatOptions;
+use cozy_uci::remark::UciRemark;
+use tokio_stream::StreamExt;
+
+use crate::engine::{Engine, EngineAnalysis, EngineAnalysisEvent, AnalysisLimit, EngineError, EngineAnalysisError};
+use crate::game::ChessGame;
+
+pub type PlayerFuture<'p, T> = Pin<Box<dyn Future<Output = Result<T, EngineError>> + 'p>>;
+
+/// Anything that can play moves in an [`EngineMatch`](crate::engine_match::EngineMatch).
+pub trait Player: Debug {
+    /// Prepares for a new game.
+    fn new_game(&mut self) -> PlayerFuture<'_, ()>;
+
+    /// Starts searching the current position of `game`. The stream ends
+    /// after yielding the best move.
+    fn analyze(&mut self, game: &ChessGame, limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError>;
+
+    /// Ends a search whose analysis stream was dropped before the best move.
+    fn stop(&mut self) -> PlayerFuture<'_, ()>;
+
+    /// Whether the player can play games that need chess960 castling.
+    fn chess960(&self) -> bool {
+        true
+    }
+
+    /// Round trip time of `pings` pings, for players that are talked to
+    /// over a channel with noticeable latency.
+    fn measure_latency(&mut self, _pings: u32) -> PlayerFuture<'_, Duration> {
+        Box::pin(async { Ok(Duration::ZERO) })
+    }
+}
+
+impl Player for Engine {
+    fn new_game(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(Engine::new_game(self))
+    }
+
+    fn analyze(&mut self, game: &ChessGame, limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError> {
+        Engine::analyze(self, game, limit)
+    }
+
+    fn stop(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(Engine::stop(self))
+    }
+
+    fn chess960(&self) -> bool {
+        self.chess960_enabled()
+    }
+
+    fn measure_latency(&mut self, pings: u32) -> PlayerFuture<'_, Duration> {
+        Box::pin(Engine::measure_latency(self, pings))
+    }
+}
+
+/// Every legal move in `board`, in move generation order.
+pub fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::new();
+    board.generate_moves(|piece_moves| {
+        moves.extend(piece_moves);
+        false
+    });
+    moves
+}
+
+/// An analysis that immediately plays `mv`, or resigns if there is none.
+fn play(mv: Option<Move>) -> EngineAnalysis<'static> {
+    let event = mv.map_or(EngineAnalysisEvent::Resign, EngineAnalysisEvent::BestMove);
+    EngineAnalysis::new(tokio_stream::once(Ok(event)))
+}
+
+/// Plays a uniformly random legal move. The moves only depend on the seed
+/// and the positions the player is asked to move in.
+#[derive(Debug, Clone)]
+pub struct RandomPlayer {
+    state: u64
+}
+
+impl RandomPlayer {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// SplitMix64.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Player for RandomPlayer {
+    fn new_game(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn analyze(&mut self, game: &ChessGame, _limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError> {
+        let moves = legal_moves(game.board());
+        let mv = (!moves.is_empty()).then(|| moves[(self.next_u64() % moves.len() as u64) as usize]);
+        Ok(play(mv))
+    }
+
+    fn stop(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Always plays the first legal move in move generation order.
+#[derive(Debug, Clone, Default)]
+pub struct FirstMovePlayer;
+
+impl Player for FirstMovePlayer {
+    fn new_game(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn analyze(&mut self, game: &ChessGame, _limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError> {
+        Ok(play(legal_moves(game.board()).first().copied()))
+    }
+
+    fn stop(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Plays a fixed list of moves in order, whether they are legal or not,
+/// and resigns once it runs out. Starts over from the first move every game.
+#[derive(Debug, Clone)]
+pub struct ScriptedPlayer {
+    moves: Vec<(Move, Option<i32>)>,
+    next: usize
+}
+
+impl ScriptedPlayer {
+    pub fn new(moves: Vec<Move>) -> Self {
+        Self::with_scores(moves.into_iter().map(|mv| (mv, None)).collect())
+    }
+
+    /// Reports each move's score in centipawns, from the player's own point
+    /// of view, in an info before playing it.
+    pub fn with_scores(moves: Vec<(Move, Option<i32>)>) -> Self {
+        Self { moves, next: 0 }
+    }
+}
+
+impl Player for ScriptedPlayer {
+    fn new_game(&mut self) -> PlayerFuture<'_, ()> {
+        self.next = 0;
+        Box::pin(async { Ok(()) })
+    }
+
+    fn analyze(&mut self, _game: &ChessGame, _limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError> {
+        let (mv, score) = match self.moves.get(self.next) {
+            Some(&(mv, score)) => (Some(mv), score),
+            None => (None, None)
+        };
+        self.next += 1;
+        let info = score.map(|cp| {
+            // Parsed rather than built so it matches what an engine would send.
+            let options = UciFormatOptions { chess960: false, wdl: false };
+            match UciRemark::parse_from(&format!("info depth 1 score cp {}", cp), &options) {
+                Ok(UciRemark::Info(info)) => EngineAnalysisEvent::Info(info),
+                rmk => panic!("unexpected remark {:?}", rmk)
+            }
+        });
+        Ok(EngineAnalysis::new(tokio_stream::iter(info.map(Ok)).chain(play(mv))))
+    }
+
+    fn stop(&mut self) -> PlayerFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::adjudication::*;
+    use crate::engine_match::*;
+
+    fn moves(moves: &[&str]) -> Vec<Move> {
+        moves.iter().map(|mv| mv.parse().unwrap()).collect()
+    }
+
+    /// A scripted player that reports `score` with every move.
+    fn scored(script: &[&str], score: i32) -> ScriptedPlayer {
+        ScriptedPlayer::with_scores(moves(script).into_iter().map(|mv| (mv, Some(score))).collect())
+    }
+
+    /// Plays a game from the start position without time limits, returning
+    /// the moves played, the winner and the termination.
+    async fn play_game<'p>(
+        white: &'p mut dyn Player,
+        black: &'p mut dyn Player,
+        adjudicators: Vec<Box<dyn Adjudicator>>
+    ) -> (Vec<Move>, Option<Color>, GameTermination) {
+        let time_control = || EngineMatchTimeConfig {
+            search_limit: None,
+            clock: ChessClockState::Infinite
+        };
+        let config = EngineMatchConfig {
+            white_time_control: time_control(),
+            black_time_control: time_control(),
+            time_margin: Duration::ZERO,
+            latency_pings: 0,
+            compensate_latency: false,
+            adjudicators
+        };
+        let game = ChessGame::new(Board::default());
+        let engine_match = EngineMatch::new(config, game, white, black).unwrap();
+        let events = engine_match.run();
+        tokio::pin!(events);
+        let mut played = Vec::new();
+        while let Some(event) = events.next().await {
+            match event.unwrap() {
+                EngineMatchEvent::MovePlayed { mv, .. } => played.push(mv),
+                EngineMatchEvent::GameOver { winner, termination, .. } => return (played, winner, termination),
+                _ => {}
+            }
+        }
+        panic!("game ended without a result");
+    }
+
+    /// Asks to take back a move every time it is asked to search.
+    #[derive(Debug)]
+    struct TakebackPlayer;
+
+    impl Player for TakebackPlayer {
+        fn new_game(&mut self) -> PlayerFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn analyze(&mut self, _game: &ChessGame, _limit: AnalysisLimit) -> Result<EngineAnalysis<'_>, EngineAnalysisError> {
+            Ok(EngineAnalysis::new(tokio_stream::once(Ok(EngineAnalysisEvent::Takeback))))
+        }
+
+        fn stop(&mut self) -> PlayerFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn max_moves(max_plies: u32) -> Vec<Box<dyn Adjudicator>> {
+        vec![Box::new(MaxMovesAdjudicator::new(MaxMovesAdjudication { max_plies, score: None }))]
+    }
+
+    #[tokio::test]
+    async fn scripted_checkmate() {
+        let mut white = ScriptedPlayer::new(moves(&["f2f3", "g2g4"]));
+        let mut black = ScriptedPlayer::new(moves(&["e7e5", "d8h4"]));
+        let (played, winner, termination) = play_game(&mut white, &mut black, Vec::new()).await;
+        assert_eq!(played.len(), 4);
+        assert_eq!(winner, Some(Color::Black));
+        assert!(matches!(termination, GameTermination::Normal));
+    }
+
+    #[tokio::test]
+    async fn scripted_illegal_move_loses() {
+        let mut white = ScriptedPlayer::new(moves(&["e2e4", "e4e6"]));
+        let mut black = ScriptedPlayer::new(moves(&["e7e5"]));
+        let (played, winner, termination) = play_game(&mut white, &mut black, Vec::new()).await;
+        assert_eq!(played.len(), 2);
+        assert_eq!(winner, Some(Color::Black));
+        assert!(matches!(termination, GameTermination::IllegalMove { mv } if mv == "e4e6".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn scripted_player_resigns_when_out_of_moves() {
+        let mut white = ScriptedPlayer::new(moves(&["e2e4"]));
+        let mut black = ScriptedPlayer::new(moves(&["e7e5", "g8f6"]));
+        let (played, winner, termination) = play_game(&mut white, &mut black, Vec::new()).await;
+        assert_eq!(played.len(), 2);
+        assert_eq!(winner, Some(Color::Black));
+        assert!(matches!(termination, GameTermination::Resignation));
+
+        let (replayed, _, _) = play_game(&mut white, &mut black, Vec::new()).await;
+        assert_eq!(played, replayed);
+    }
+
+    #[tokio::test]
+    async fn random_player_is_seeded() {
+        let game = |seed| async move {
+            let mut white = RandomPlayer::new(seed);
+            let mut black = RandomPlayer::new(seed + 1);
+            play_game(&mut white, &mut black, max_moves(40)).await.0
+        };
+        let played = game(1).await;
+        assert_eq!(played, game(1).await);
+        assert_ne!(played, game(2).await);
+    }
+
+    #[tokio::test]
+    async fn max_moves_adjudication() {
+        let mut white = ScriptedPlayer::new(moves(&["e2e4", "g1f3", "f1c4", "b1c3", "d2d3"]));
+        let mut black = ScriptedPlayer::new(moves(&["e7e5", "b8c6", "f8c5", "g8f6", "d7d6"]));
+        let (played, winner, termination) = play_game(&mut white, &mut black, max_moves(10)).await;
+        assert_eq!(played.len(), 10);
+        assert_eq!(winner, None);
+        assert!(matches!(termination, GameTermination::MaxMoves));
+    }
+
+    #[tokio::test]
+    async fn resign_adjudication() {
+        let mut white = scored(&["e2e4", "g1f3", "f1c4"], 500);
+        let mut black = scored(&["e7e5", "b8c6", "f8c5"], -500);
+        let adjudicators: Vec<Box<dyn Adjudicator>> = vec![Box::new(ResignAdjudicator::new(ResignAdjudication {
+            score: 400,
+            move_count: 2
+        }))];
+        let (played, winner, termination) = play_game(&mut white, &mut black, adjudicators).await;
+        assert_eq!(played.len(), 4);
+        assert_eq!(winner, Some(Color::White));
+        assert!(matches!(termination, GameTermination::Adjudication));
+    }
+
+    #[tokio::test]
+    async fn resign_adjudication_needs_both_sides_to_agree() {
+        let mut white = scored(&["e2e4", "g1f3", "f1c4"], 500);
+        let mut black = scored(&["e7e5", "b8c6", "f8c5"], 0);
+        let adjudicators: Vec<Box<dyn Adjudicator>> = vec![Box::new(ResignAdjudicator::new(ResignAdjudication {
+            score: 400,
+            move_count: 2
+        }))];
+        let (played, winner, termination) = play_game(&mut white, &mut black, adjudicators).await;
+        assert_eq!(played.len(), 6);
+        assert_eq!(winner, Some(Color::Black));
+        assert!(matches!(termination, GameTermination::Resignation));
+    }
+
+    #[tokio::test]
+    async fn draw_adjudication() {
+        let mut white = scored(&["g1f3", "f3g1", "g1f3"], 5);
+        let mut black = scored(&["g8f6", "f6g8", "g8f6"], -5);
+        let adjudicators: Vec<Box<dyn Adjudicator>> = vec![Box::new(DrawAdjudicator::new(DrawAdjudication {
+            min_move: 3,
+            score: 10,
+            move_count: 2,
+            reset_on_irreversible: false
+        }))];
+        let (played, winner, termination) = play_game(&mut white, &mut black, adjudicators).await;
+        assert_eq!(played.len(), 4);
+        assert_eq!(winner, None);
+        assert!(matches!(termination, GameTermination::Adjudication));
+    }
+
+    #[tokio::test]
+    async fn empty_script_resigns_immediately() {
+        let mut white = FirstMovePlayer;
+        let mut black = ScriptedPlayer::new(Vec::new());
+        let (played, winner, termination) = play_game(&mut white, &mut black, Vec::new()).await;
+        assert_eq!(played.len(), 1);
+        assert_eq!(winner, Some(Color::White));
+        assert!(matches!(termination, GameTermination::Resignation));
+    }
+
+    #[tokio::test]
+    async fn takeback_without_moves_loses() {
+        let mut white = TakebackPlayer;
+        let mut black = FirstMovePlayer;
+        let (played, winner, termination) = play_game(&mut white, &mut black, Vec::new()).await;
+        assert!(played.is_empty());
+        assert_eq!(winner, Some(Color::Black));
+        assert!(matches!(termination, GameTermination::EngineError { .. }));
+    }
+}
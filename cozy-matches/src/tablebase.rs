@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use cozy_chess::*;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty::fen::Fen;
+use shakmaty_syzygy::Wdl;
+
+use crate::adjudication::{Adjudicator, AdjudicationContext};
+use crate::engine_match::GameTermination;
+
+/// Syzygy WDL tables loaded from local `.rtbw` files.
+pub struct Tablebase {
+    tables: shakmaty_syzygy::Tablebase<Chess>
+}
+
+impl std::fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tablebase")
+            .field("max_pieces", &self.max_pieces())
+            .finish()
+    }
+}
+
+impl Tablebase {
+    pub fn open(directories: &[impl AsRef<Path>]) -> std::io::Result<Self> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        for directory in directories {
+            tables.add_directory(directory)?;
+        }
+        Ok(Self { tables })
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    /// Probes the winner of `board`, with `Some(None)` meaning a draw.
+    /// Cursed wins and blessed losses count as draws. The result assumes the
+    /// halfmove clock was just reset, so it is only exact after a capture or
+    /// pawn move. Returns `None` if the position is not in the tables.
+    pub fn probe_winner(&self, board: &Board) -> Option<Option<Color>> {
+        if board.occupied().len() as usize > self.max_pieces() {
+            return None;
+        }
+        let fen: Fen = board.to_string().parse().ok()?;
+        let position: Chess = fen.into_position(CastlingMode::Chess960).ok()?;
+        let stm = board.side_to_move();
+        match self.tables.probe_wdl_after_zeroing(&position).ok()? {
+            Wdl::Win => Some(Some(stm)),
+            Wdl::Loss => Some(Some(!stm)),
+            Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => Some(None)
+        }
+    }
+}
+
+/// Ends the game with the tablebase result right after a capture or pawn
+/// move, where the 50-move rule can't change the result.
+#[derive(Debug, Clone)]
+pub struct TablebaseAdjudicator {
+    tablebase: Arc<Tablebase>
+}
+
+impl TablebaseAdjudicator {
+    pub fn new(tablebase: Arc<Tablebase>) -> Self {
+        Self { tablebase }
+    }
+}
+
+impl Adjudicator for TablebaseAdjudicator {
+    fn adjudicate(&mut self, context: &AdjudicationContext) -> Option<(Option<Color>, GameTermination)> {
+        let board = context.game.board();
+        if board.halfmove_clock() != 0 {
+            return None;
+        }
+        let winner = self.tablebase.probe_winner(board)?;
+        Some((winner, GameTermination::Tablebase))
+    }
+}
@@ -3,28 +3,154 @@ use std::time::Duration;
 
 use thiserror::Error;
 
-#[derive(Debug, Clone, Copy)]
+/// A sequence of time control periods, such as `40/90m+30s:30m+30s`.
+/// Increments prefixed with `b` are Bronstein delays and ones prefixed with
+/// `d` are simple delays, as in `5m+d3s`.
+/// Once the last period's moves are played it starts over, so `40/5m`
+/// adds five minutes every forty moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TimeControl {
+    pub periods: Vec<TimeControlPeriod>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControlPeriod {
+    /// Moves to play in this period, or `None` for the rest of the game.
+    pub moves: Option<u32>,
+    /// Time added at the start of this period.
     pub time: Duration,
-    pub increment: Duration
+    /// Time added after every move in this period, or the delay for delay controls.
+    pub increment: Duration,
+    pub increment_kind: IncrementKind
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncrementKind {
+    /// The full increment is added after every move.
+    Fischer,
+    /// After every move, the time used is given back, up to the increment.
+    Bronstein,
+    /// The clock only starts running once the increment has passed.
+    SimpleDelay
+}
+
+impl IncrementKind {
+    /// Splits the time a move took into the time charged to the clock and
+    /// the time given back afterwards.
+    pub fn charge(self, elapsed: Duration, increment: Duration) -> (Duration, Duration) {
+        match self {
+            Self::Fischer => (elapsed, increment),
+            Self::Bronstein => (elapsed, increment.min(elapsed)),
+            Self::SimpleDelay => (elapsed.saturating_sub(increment), Duration::ZERO)
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Fischer => "",
+            Self::Bronstein => "b",
+            Self::SimpleDelay => "d"
+        }
+    }
+}
+
+impl TimeControl {
+    pub fn new(time: Duration, increment: Duration) -> Self {
+        Self {
+            periods: vec![TimeControlPeriod {
+                moves: None,
+                time,
+                increment,
+                increment_kind: IncrementKind::Fischer
+            }]
+        }
+    }
+
+    /// The period following `index`.
+    pub fn next_period(&self, index: usize) -> usize {
+        (index + 1).min(self.periods.len() - 1)
+    }
+}
+
+impl std::fmt::Display for TimeControlPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(moves) = self.moves {
+            write!(f, "{}/", moves)?;
+        }
+        write!(f, "{}+{}{}", self.time.as_secs_f64(), self.increment_kind.prefix(), self.increment.as_secs_f64())
+    }
+}
+
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, period) in self.periods.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{}", period)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error, Clone, Copy)]
 #[error("invalid time control")]
 pub struct InvalidTimeControl;
 
-impl FromStr for TimeControl {
+impl FromStr for TimeControlPeriod {
     type Err = InvalidTimeControl;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (time, increment) = s.split_once('+').ok_or(InvalidTimeControl)?;
+        let (moves, s) = match s.split_once('/') {
+            Some((moves, s)) => (Some(moves.parse().map_err(|_| InvalidTimeControl)?), s),
+            None => (None, s)
+        };
+        if moves == Some(0) {
+            Err(InvalidTimeControl)?;
+        }
+        let (time, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let (increment_kind, increment) = if let Some(delay) = increment.strip_prefix('b') {
+            (IncrementKind::Bronstein, delay)
+        } else if let Some(delay) = increment.strip_prefix('d') {
+            (IncrementKind::SimpleDelay, delay)
+        } else {
+            (IncrementKind::Fischer, increment)
+        };
         let time = parse_duration(time).ok_or(InvalidTimeControl)?;
         let increment = parse_duration(increment).ok_or(InvalidTimeControl)?;
-        Ok(TimeControl { time, increment })
+        Ok(TimeControlPeriod { moves, time, increment, increment_kind })
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = InvalidTimeControl;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let periods = s.split(':')
+            .map(str::parse)
+            .collect::<Result<Vec<TimeControlPeriod>, _>>()?;
+        Ok(TimeControl { periods })
+    }
+}
+
+/// Serialized as the same string it is parsed from.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TimeControl {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TimeControl {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|_| serde::de::Error::custom(format!("invalid time control {:?}", s)))
     }
 }
 
-fn parse_duration(s: &str) -> Option<Duration> {
+/// Parses a duration like `90m`, `30s`, `100ms` or a plain number of seconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
     fn secs(s: &str) -> Option<Duration> {
         let secs: f64 = s.parse().ok()?;
         if secs.is_sign_negative() || !secs.is_finite() || secs >= u64::MAX as f64 {
@@ -37,7 +163,7 @@ fn parse_duration(s: &str) -> Option<Duration> {
         return Some(secs(s)? / 1000);
     }
     if let Some(s) = s.strip_suffix("s") {
-        return Some(secs(s)?);
+        return secs(s);
     }
     if let Some(s) = s.strip_suffix("m") {
         return Some(secs(s)? * 60);
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use crate::engine_match::ChessClockState;
+
+/// Moves leaving less than this fraction of the starting time on the clock count as near flags.
+const NEAR_FLAG_FRACTION: f64 = 0.02;
+const CLOCK_USAGE_MOVE: u32 = 40;
+
+/// How an engine spent its time, over one game or accumulated over several.
+#[derive(Debug, Clone, Default)]
+pub struct TimeUsage {
+    pub moves: u32,
+    /// Wall time spent on all moves.
+    pub total_time: Duration,
+    /// The most a move took beyond a fixed move time.
+    pub max_overshoot: Duration,
+    /// Moves that left the clock almost empty.
+    pub near_flags: u32,
+    clock_used_sum: f64,
+    clock_used_games: u32,
+    charged_this_game: Duration
+}
+
+impl TimeUsage {
+    pub fn average_time(&self) -> Option<Duration> {
+        (self.moves > 0).then(|| self.total_time / self.moves)
+    }
+
+    /// The average fraction of the available clock time used by move 40,
+    /// over the games that lasted that long.
+    pub fn clock_used_by_move_40(&self) -> Option<f64> {
+        (self.clock_used_games > 0).then(|| self.clock_used_sum / self.clock_used_games as f64)
+    }
+
+    /// Records a move that took `elapsed` and was charged `charged`, given
+    /// the clock before and after the move. `moves` counts the move.
+    pub(crate) fn record(
+        &mut self,
+        state: &ChessClockState,
+        moves: u32,
+        elapsed: Duration,
+        charged: Duration,
+        before: Option<Duration>,
+        after: Option<Duration>
+    ) {
+        self.moves += 1;
+        self.total_time += elapsed;
+        if let ChessClockState::MoveTime(move_time) = state {
+            self.max_overshoot = self.max_overshoot.max(elapsed.saturating_sub(*move_time));
+        }
+        if let (Some(time_control), Some(before), Some(after)) = (state.time_control(), before, after) {
+            let start = time_control.periods[0].time;
+            if before.saturating_sub(charged).as_secs_f64() < start.as_secs_f64() * NEAR_FLAG_FRACTION {
+                self.near_flags += 1;
+            }
+            self.charged_this_game += charged;
+            if moves == CLOCK_USAGE_MOVE {
+                let used = self.charged_this_game.as_secs_f64();
+                self.clock_used_sum += used / (used + after.as_secs_f64()).max(f64::EPSILON);
+                self.clock_used_games += 1;
+            }
+        }
+    }
+
+    /// Adds the statistics of another game.
+    pub fn merge(&mut self, other: &TimeUsage) {
+        self.moves += other.moves;
+        self.total_time += other.total_time;
+        self.max_overshoot = self.max_overshoot.max(other.max_overshoot);
+        self.near_flags += other.near_flags;
+        self.clock_used_sum += other.clock_used_sum;
+        self.clock_used_games += other.clock_used_games;
+    }
+}
+
+impl std::fmt::Display for TimeUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let average = self.average_time().unwrap_or_default();
+        write!(f, "{} moves, {:.3}s/move", self.moves, average.as_secs_f64())?;
+        if let Some(used) = self.clock_used_by_move_40() {
+            write!(f, ", {:.0}% of clock used by move {}", used * 100.0, CLOCK_USAGE_MOVE)?;
+        }
+        if !self.max_overshoot.is_zero() {
+            write!(f, ", max movetime overshoot {:.3}s", self.max_overshoot.as_secs_f64())?;
+        }
+        write!(f, ", {} near flags", self.near_flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Color;
+
+    use super::*;
+    use crate::clock::ChessClock;
+
+    /// Plays `moves` moves for white that each take `elapsed`.
+    fn play(time_control: &str, moves: u32, elapsed: Duration) -> TimeUsage {
+        let state = ChessClockState::Clock(time_control.parse().unwrap());
+        let mut clock = ChessClock::new(state.clone(), state, Duration::ZERO);
+        let mut usage = TimeUsage::default();
+        for _ in 0..moves {
+            let before = clock.time_left(Color::White);
+            let update = clock.update(Color::White, elapsed);
+            usage.record(clock.state(Color::White), clock.moves(Color::White), elapsed, elapsed, before, update.time_left);
+        }
+        usage
+    }
+
+    #[test]
+    fn clock_used_by_move_40_in_sudden_death() {
+        let usage = play("100", 40, Duration::from_secs(1));
+        assert!((usage.clock_used_by_move_40().unwrap() - 0.4).abs() < 1e-9);
+        assert_eq!(usage.near_flags, 0);
+    }
+
+    #[test]
+    fn clock_used_by_move_40_ignores_next_period() {
+        let usage = play("40/60:30", 40, Duration::from_secs(1));
+        assert!((usage.clock_used_by_move_40().unwrap() - 40.0 / 60.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn clock_used_by_move_40_with_whole_period_used() {
+        let usage = play("40/40:30", 40, Duration::from_millis(990));
+        assert!((usage.clock_used_by_move_40().unwrap() - 0.99).abs() < 1e-9);
+        assert_eq!(usage.moves, 40);
+        assert_eq!(usage.near_flags, 1);
+    }
+}
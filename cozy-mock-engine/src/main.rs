@@ -0,0 +1,149 @@
+//! A fake UCI engine for testing. Each argument is one step of a script
+//! that is run from start to finish:
+//!
+//! - `expect <command>` waits for a command whose first words are the words
+//!   of `<command>`, such as `expect go` or `expect go infinite`. `expect uci`
+//!   doesn't match `ucinewgame`.
+//! - `send <line>` writes a line to stdout, verbatim.
+//! - `stderr <line>` writes a line to stderr.
+//! - `sleep <ms>` waits for that many milliseconds.
+//! - `exit <code>` exits immediately.
+//! - `ignore isready` stops answering `isready` from then on.
+//! - `play` ends the script and plays like a well behaved engine: `go` is
+//!   answered with the first legal move, or once `stop` is sent for
+//!   `go infinite`. `UCI_Chess960` is supported.
+//!
+//! While waiting for a command, `isready` is answered with `readyok` and
+//! anything else is ignored. Once the script ends, the engine keeps answering
+//! `isready` until its stdin is closed or it receives `quit`.
+//!
+//! For example, a well behaved engine that plays a single move:
+//!
+//! ```text
+//! cozy-mock-engine "expect uci" "send id name Mock" "send id author Mock" "send uciok" \
+//!     "expect go" "send info depth 1 score cp 30 pv e2e4" "send bestmove e2e4"
+//! ```
+
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use std::time::Duration;
+
+use cozy_chess::{Board, File, Move, Piece, Square};
+
+/// Reads commands until one starts with the words of `expected`. Never
+/// returns if `expected` is `None`.
+fn wait_for(commands: &mut impl Iterator<Item = String>, expected: Option<&str>, answer_isready: bool) {
+    loop {
+        let command = commands.next().unwrap_or_else(|| exit(0));
+        let mut words = command.split_whitespace();
+        if let Some(expected) = expected {
+            if expected.split_whitespace().all(|word| words.next() == Some(word)) {
+                return;
+            }
+        }
+        let name = command.split_whitespace().next().unwrap_or("");
+        match name {
+            "isready" if answer_isready => send("readyok"),
+            "quit" => exit(0),
+            _ => {}
+        }
+    }
+}
+
+/// Parses the arguments of a `position` command. Standard castling moves
+/// are converted to king takes rook unless `chess960` is set.
+fn parse_position<'a>(mut words: impl Iterator<Item = &'a str>, chess960: bool) -> Board {
+    let mut board = match words.next() {
+        Some("fen") => {
+            let fen = words.by_ref().take(6).collect::<Vec<_>>().join(" ");
+            Board::from_fen(&fen, false)
+                .or_else(|_| Board::from_fen(&fen, true))
+                .unwrap()
+        }
+        _ => Board::default()
+    };
+    for mv in words.skip_while(|&word| word != "moves").skip(1) {
+        let mut mv: Move = mv.parse().unwrap();
+        let castles = board.piece_on(mv.from) == Some(Piece::King)
+            && mv.from.file() == File::E
+            && matches!(mv.to.file(), File::C | File::G);
+        if !chess960 && castles {
+            let file = if mv.to.file() == File::G { File::H } else { File::A };
+            mv.to = Square::new(file, mv.to.rank());
+        }
+        board.play(mv);
+    }
+    board
+}
+
+/// The first legal move in `board` as a `bestmove` line.
+fn best_move(board: &Board, chess960: bool) -> String {
+    let mut best = None;
+    board.generate_moves(|moves| {
+        best = moves.into_iter().next();
+        best.is_some()
+    });
+    let mut mv = match best {
+        Some(mv) => mv,
+        None => return "bestmove 0000".to_owned()
+    };
+    if !chess960 && board.color_on(mv.to) == board.color_on(mv.from) {
+        let file = if mv.to.file() > mv.from.file() { File::G } else { File::C };
+        mv.to = Square::new(file, mv.to.rank());
+    }
+    format!("bestmove {}", mv)
+}
+
+/// Plays like a well behaved engine until stdin is closed or `quit` is sent.
+fn play(commands: &mut impl Iterator<Item = String>, answer_isready: bool) -> ! {
+    let mut board = Board::default();
+    let mut chess960 = false;
+    let mut searching = false;
+    for command in commands {
+        let mut words = command.split_whitespace();
+        match words.next() {
+            Some("isready") if answer_isready => send("readyok"),
+            Some("setoption") => {
+                if let ["name", "UCI_Chess960", "value", value] = words.collect::<Vec<_>>()[..] {
+                    chess960 = value == "true";
+                }
+            }
+            Some("position") => board = parse_position(words, chess960),
+            Some("go") if words.any(|word| word == "infinite") => searching = true,
+            Some("go") => send(&best_move(&board, chess960)),
+            Some("stop") if searching => {
+                searching = false;
+                send(&best_move(&board, chess960));
+            }
+            Some("quit") => break,
+            _ => {}
+        }
+    }
+    exit(0)
+}
+
+fn send(line: &str) {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line).unwrap();
+    stdout.flush().unwrap();
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut commands = stdin.lock().lines().map_while(Result::ok);
+    let mut answer_isready = true;
+    for step in std::env::args().skip(1) {
+        let (action, arg) = step.split_once(' ').unwrap_or((&step, ""));
+        match action {
+            "expect" => wait_for(&mut commands, Some(arg), answer_isready),
+            "send" => send(arg),
+            "stderr" => eprintln!("{}", arg),
+            "sleep" => std::thread::sleep(Duration::from_millis(arg.parse().unwrap())),
+            "exit" => exit(arg.parse().unwrap()),
+            "ignore" if arg == "isready" => answer_isready = false,
+            "play" => play(&mut commands, answer_isready),
+            _ => panic!("unknown script step `{}`", step)
+        }
+    }
+    wait_for(&mut commands, None, answer_isready);
+}
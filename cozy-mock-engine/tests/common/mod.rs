@@ -0,0 +1,34 @@
+// Each test crate only uses some of these.
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use cozy_matches::engine::{Engine, EngineError};
+
+/// The steps of a handshake advertising `options`.
+pub fn handshake(options: &[&str]) -> Vec<String> {
+    let mut steps = vec![
+        "expect uci".to_owned(),
+        "send id name Mock".to_owned(),
+        "send id author Cozy".to_owned()
+    ];
+    steps.extend(options.iter().map(|option| format!("send option {}", option)));
+    steps.push("send uciok".to_owned());
+    steps
+}
+
+pub fn mock_path() -> &'static Path {
+    Path::new(env!("CARGO_BIN_EXE_cozy-mock-engine"))
+}
+
+/// Starts a mock engine running `steps`.
+pub async fn mock(steps: &[String]) -> Result<(Engine, Vec<EngineError>), EngineError> {
+    Engine::new(mock_path(), steps, None).await
+}
+
+/// Starts a mock engine that completes the handshake and then runs `steps`.
+pub async fn mock_after_handshake(steps: &[&str]) -> Engine {
+    let mut script = handshake(&[]);
+    script.extend(steps.iter().map(|&step| step.to_owned()));
+    mock(&script).await.unwrap().0
+}
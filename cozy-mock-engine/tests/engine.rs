@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use cozy_chess::*;
+use cozy_matches::engine::*;
+use cozy_matches::game::ChessGame;
+use tokio_stream::StreamExt;
+
+mod common;
+
+use common::{handshake, mock, mock_after_handshake};
+
+fn steps(steps: &[&str]) -> Vec<String> {
+    steps.iter().map(|&step| step.to_owned()).collect()
+}
+
+fn depth_limit(depth: u32) -> AnalysisLimit {
+    AnalysisLimit {
+        search_limit: Some(AnalysisSearchLimit { nodes: None, depth: Some(depth) }),
+        time_limit: None
+    }
+}
+
+async fn analyze(engine: &mut Engine) -> Vec<Result<EngineAnalysisEvent, EngineError>> {
+    let game = ChessGame::new(Board::default());
+    engine.analyze(&game, depth_limit(1)).unwrap().collect().await
+}
+
+#[tokio::test]
+async fn handshake_reads_options() {
+    let (engine, warnings) = mock(&handshake(&[
+        "name Hash type spin default 16 min 1 max 1024",
+        "name Ponder type check default false",
+        "name Style type combo default Normal var Solid var Normal var Risky",
+        "name Book type string default book.bin"
+    ])).await.unwrap();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    let options = engine.options();
+    assert_eq!(options.get("Hash"), Some(&UciOptionField::Spin { value: 16, min: 1, max: 1024 }));
+    assert_eq!(options.get("Ponder"), Some(&UciOptionField::Check { value: false }));
+    assert!(matches!(options.get("Style"), Some(UciOptionField::Combo { value: 1, .. })));
+    assert_eq!(options.get("Book"), Some(&UciOptionField::String { value: "book.bin".to_owned() }));
+    assert!(!engine.chess960_supported());
+}
+
+#[tokio::test]
+async fn missing_id_is_a_warning() {
+    let (_, warnings) = mock(&steps(&["expect uci", "send uciok"])).await.unwrap();
+    assert!(matches!(warnings[..], [EngineError::MissingName, EngineError::MissingAuthor]));
+}
+
+#[tokio::test]
+async fn unexpected_remark_is_a_warning() {
+    let (_, warnings) = mock(&steps(&[
+        "expect uci",
+        "send id name Mock",
+        "send id author Cozy",
+        "send readyok",
+        "send uciok"
+    ])).await.unwrap();
+    assert!(matches!(warnings[..], [EngineError::UnexpectedRemark(_)]));
+}
+
+#[tokio::test]
+async fn malformed_line_fails_handshake() {
+    let result = mock(&steps(&["expect uci", "send id name Mock", "send info depth banana", "send uciok"])).await;
+    assert!(matches!(result, Err(EngineError::InvalidMessage(..))));
+}
+
+#[tokio::test]
+async fn out_of_range_default_fails_handshake() {
+    let result = mock(&handshake(&["name Hash type spin default 0 min 1 max 1024"])).await;
+    assert!(matches!(result, Err(EngineError::InvalidOption)));
+}
+
+#[tokio::test]
+async fn crash_during_handshake() {
+    let result = mock(&steps(&["expect uci", "stderr segmentation fault", "exit 3"])).await;
+    match result {
+        Err(EngineError::UnexpectedTermination(exit)) => {
+            assert_eq!(exit.status.and_then(|status| status.code()), Some(3));
+            assert_eq!(exit.stderr, ["segmentation fault"]);
+        }
+        result => panic!("expected a crash, got {:?}", result.map(|(_, warnings)| warnings))
+    }
+}
+
+#[tokio::test]
+async fn set_option_checks_advertised_options() {
+    let mut script = handshake(&[
+        "name Hash type spin default 16 min 1 max 1024",
+        "name UCI_Chess960 type check default false"
+    ]);
+    script.push("expect setoption".to_owned());
+    script.push("expect setoption".to_owned());
+    let (mut engine, _) = mock(&script).await.unwrap();
+
+    let result = engine.set_option("Threads".to_owned(), UciOptionValue::Spin(2)).await;
+    assert!(matches!(result, Err(SetOptionError::NoSuchOption)));
+    let result = engine.set_option("Hash".to_owned(), UciOptionValue::Check(true)).await;
+    assert!(matches!(result, Err(SetOptionError::TypeMismatch)));
+    let result = engine.set_option("Hash".to_owned(), UciOptionValue::Spin(2048)).await;
+    assert!(matches!(result, Err(SetOptionError::OutOfRange)));
+    assert_eq!(engine.options().get("Hash"), Some(&UciOptionField::Spin { value: 16, min: 1, max: 1024 }));
+
+    engine.set_option("Hash".to_owned(), UciOptionValue::Spin(64)).await.unwrap();
+    assert_eq!(engine.options().get("Hash"), Some(&UciOptionField::Spin { value: 64, min: 1, max: 1024 }));
+    assert!(engine.chess960_supported());
+    engine.set_option("UCI_Chess960".to_owned(), UciOptionValue::Check(true)).await.unwrap();
+    assert!(engine.chess960_enabled());
+    engine.new_game().await.unwrap();
+}
+
+#[tokio::test]
+async fn analyze_yields_info_then_best_move() {
+    let mut engine = mock_after_handshake(&[
+        "expect go",
+        "send info depth 1 score cp 30 nodes 20 pv e2e4",
+        "sleep 10",
+        "send bestmove e2e4"
+    ]).await;
+    let events = analyze(&mut engine).await;
+    match &events[..] {
+        [Ok(EngineAnalysisEvent::Info(info)), Ok(EngineAnalysisEvent::BestMove(mv))] => {
+            assert_eq!(info.depth, Some(1));
+            assert_eq!(info.nodes, Some(20));
+            assert_eq!(*mv, "e2e4".parse().unwrap());
+        }
+        events => panic!("unexpected events {:?}", events)
+    }
+    let search = engine.last_search().unwrap();
+    assert!(search.go.starts_with("go"));
+    engine.new_game().await.unwrap();
+    assert!(engine.last_search().is_none());
+}
+
+#[tokio::test]
+async fn analyze_reports_unexpected_remarks() {
+    let mut engine = mock_after_handshake(&["expect go", "send uciok", "send bestmove e2e4"]).await;
+    let events = analyze(&mut engine).await;
+    assert!(matches!(
+        events[..],
+        [Ok(EngineAnalysisEvent::EngineError(EngineError::UnexpectedRemark(_))), Ok(EngineAnalysisEvent::BestMove(_))]
+    ));
+}
+
+#[tokio::test]
+async fn analyze_fails_on_malformed_line() {
+    let mut engine = mock_after_handshake(&["expect go", "send bestmove banana", "send bestmove e2e4"]).await;
+    let events = analyze(&mut engine).await;
+    assert!(matches!(events[..], [Err(EngineError::InvalidMessage(..))]));
+}
+
+#[tokio::test]
+async fn analyze_reports_crash() {
+    let mut engine = mock_after_handshake(&["expect go", "send info depth 1", "exit 101"]).await;
+    let events = analyze(&mut engine).await;
+    match &events[..] {
+        [Ok(EngineAnalysisEvent::Info(_)), Err(EngineError::UnexpectedTermination(exit))] => {
+            assert_eq!(exit.status.and_then(|status| status.code()), Some(101));
+        }
+        events => panic!("unexpected events {:?}", events)
+    }
+}
+
+#[tokio::test]
+async fn analyze_reports_stderr_while_engine_hangs() {
+    let mut engine = mock_after_handshake(&["expect go", "stderr thinking hard"]).await;
+    let game = ChessGame::new(Board::default());
+    let mut analysis = engine.analyze(&game, depth_limit(1)).unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(5), analysis.next()).await.unwrap();
+    assert!(matches!(event, Some(Ok(EngineAnalysisEvent::Stderr(line))) if line == "thinking hard"));
+}
+
+#[tokio::test]
+async fn analyze_skips_stderr_from_before_the_search() {
+    let mut engine = mock_after_handshake(&[
+        "expect isready",
+        "stderr loading",
+        "sleep 50",
+        "send readyok",
+        "expect go",
+        "send bestmove e2e4"
+    ]).await;
+    engine.ping().await.unwrap();
+    let events = analyze(&mut engine).await;
+    assert!(matches!(events[..], [Ok(EngineAnalysisEvent::BestMove(_))]), "{:?}", events);
+    assert_eq!(engine.recent_stderr(), ["loading"]);
+}
+
+#[tokio::test]
+async fn stop_waits_for_best_move() {
+    let mut engine = mock_after_handshake(&[
+        "expect go",
+        "send info depth 1",
+        "expect stop",
+        "sleep 10",
+        "send bestmove e2e4"
+    ]).await;
+    let game = ChessGame::new(Board::default());
+    let limit = AnalysisLimit {
+        search_limit: None,
+        time_limit: Some(AnalysisTimeLimit::Infinite)
+    };
+    {
+        let mut analysis = engine.analyze(&game, limit).unwrap();
+        assert!(matches!(analysis.next().await, Some(Ok(EngineAnalysisEvent::Info(_)))));
+    }
+    engine.stop().await.unwrap();
+    engine.new_game().await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_gives_up_on_unresponsive_engine() {
+    let mut engine = mock_after_handshake(&["expect go", "send info depth 1"]).await;
+    let game = ChessGame::new(Board::default());
+    let limit = AnalysisLimit {
+        search_limit: None,
+        time_limit: Some(AnalysisTimeLimit::Infinite)
+    };
+    {
+        let mut analysis = engine.analyze(&game, limit).unwrap();
+        assert!(matches!(analysis.next().await, Some(Ok(EngineAnalysisEvent::Info(_)))));
+    }
+    let result = engine.stop().await;
+    assert!(matches!(result, Err(EngineError::Timeout(timeout)) if timeout == STOP_TIMEOUT));
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use cozy_chess::*;
+use cozy_matches::engine::Engine;
+use cozy_matches::engine_match::*;
+use cozy_matches::game::ChessGame;
+use cozy_matches::player::{Player, ScriptedPlayer};
+use tokio_stream::StreamExt;
+
+mod common;
+
+use common::mock_after_handshake;
+
+fn config(clock: ChessClockState) -> EngineMatchConfig {
+    let time_control = || EngineMatchTimeConfig {
+        search_limit: None,
+        clock: clock.clone()
+    };
+    EngineMatchConfig {
+        white_time_control: time_control(),
+        black_time_control: time_control(),
+        time_margin: Duration::ZERO,
+        latency_pings: 0,
+        compensate_latency: false,
+        adjudicators: Vec::new()
+    }
+}
+
+/// Plays a game with the mock engine as white against a scripted black.
+async fn play_mock(steps: &[&str], black_moves: &[&str], clock: ChessClockState) -> Result<(Option<Color>, GameTermination), EngineMatchError> {
+    let mut white = mock_after_handshake(steps).await;
+    let mut black = ScriptedPlayer::new(black_moves.iter().map(|mv| mv.parse().unwrap()).collect());
+    let config = config(clock);
+    let (white, black): (&mut dyn Player, &mut dyn Player) = (&mut white, &mut black);
+    let engine_match = EngineMatch::new(config, ChessGame::new(Board::default()), white, black).unwrap();
+    let events = engine_match.run();
+    tokio::pin!(events);
+    while let Some(event) = events.next().await {
+        if let EngineMatchEvent::GameOver { winner, termination, .. } = event? {
+            return Ok((winner, termination));
+        }
+    }
+    panic!("game ended without a result");
+}
+
+#[tokio::test]
+async fn engine_plays_to_checkmate() {
+    let (winner, termination) = play_mock(
+        &["expect go", "send bestmove e2e4", "expect go", "send bestmove f1c4", "expect go", "send bestmove d1h5", "expect go", "send bestmove h5f7"],
+        &["e7e5", "b8c6", "g8f6"],
+        ChessClockState::Infinite
+    ).await.unwrap();
+    assert_eq!(winner, Some(Color::White));
+    assert!(matches!(termination, GameTermination::Normal));
+}
+
+#[tokio::test]
+async fn crash_loses() {
+    let (winner, termination) = play_mock(
+        &["expect go", "send bestmove e2e4", "expect go", "stderr out of memory", "exit 134"],
+        &["e7e5"],
+        ChessClockState::Infinite
+    ).await.unwrap();
+    assert_eq!(winner, Some(Color::Black));
+    match termination {
+        GameTermination::Crash { exit } => assert_eq!(exit.stderr, ["out of memory"]),
+        termination => panic!("expected a crash, got {:?}", termination)
+    }
+}
+
+#[tokio::test]
+async fn illegal_move_loses() {
+    let (winner, termination) = play_mock(
+        &["expect go", "send bestmove e2e5"],
+        &[],
+        ChessClockState::Infinite
+    ).await.unwrap();
+    assert_eq!(winner, Some(Color::Black));
+    assert!(matches!(termination, GameTermination::IllegalMove { mv } if mv == "e2e5".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn slow_move_forfeits() {
+    let (winner, termination) = play_mock(
+        &["expect go", "sleep 200", "send bestmove e2e4"],
+        &["e7e5"],
+        ChessClockState::MoveTime(Duration::from_millis(20))
+    ).await.unwrap();
+    assert_eq!(winner, Some(Color::Black));
+    assert!(matches!(termination, GameTermination::TimeForfeit));
+}
+
+#[tokio::test]
+async fn malformed_line_loses() {
+    let (winner, termination) = play_mock(
+        &["expect go", "send bestmove banana"],
+        &[],
+        ChessClockState::Infinite
+    ).await.unwrap();
+    assert_eq!(winner, Some(Color::Black));
+    assert!(matches!(termination, GameTermination::EngineError { .. }));
+    assert!(termination.is_failure());
+}
+
+#[tokio::test]
+async fn crash_while_measuring_latency_loses() {
+    // The first isready is from starting the new game.
+    let mut white = mock_after_handshake(&["expect isready", "send readyok", "expect isready", "exit 1"]).await;
+    let mut black = mock_after_handshake(&[]).await;
+    let mut config = config(ChessClockState::Infinite);
+    config.latency_pings = 3;
+    let engine_match: EngineMatch<Engine> = EngineMatch::new(config, ChessGame::new(Board::default()), &mut white, &mut black).unwrap();
+    let events: Vec<_> = engine_match.run().collect().await;
+    match events.last() {
+        Some(Ok(EngineMatchEvent::GameOver { winner, termination, .. })) => {
+            assert_eq!(*winner, Some(Color::Black));
+            assert!(matches!(termination, GameTermination::Crash { .. }));
+        }
+        event => panic!("expected the game to end, got {:?}", event)
+    }
+}
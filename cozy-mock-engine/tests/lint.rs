@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use cozy_matches::lint::*;
+
+mod common;
+
+use common::{handshake, mock_path};
+
+/// Lints a mock engine that advertises an option and Chess960 support,
+/// then runs `steps`.
+async fn lint(steps: &[&str]) -> LintReport {
+    let mut script = handshake(&[
+        "name Hash type spin default 16 min 1 max 1024",
+        "name UCI_Chess960 type check default false"
+    ]);
+    script.extend(steps.iter().map(|&step| step.to_owned()));
+    lint_script(&script).await
+}
+
+async fn lint_script(script: &[String]) -> LintReport {
+    let options = LintOptions {
+        timeout: Duration::from_secs(1),
+        stop_timeout: Duration::from_millis(100)
+    };
+    run("mock", mock_path(), script, &options).await
+}
+
+fn scenario<'r>(report: &'r LintReport, name: &str) -> &'r ScenarioReport {
+    report.scenarios.iter().find(|scenario| scenario.name == name).unwrap()
+}
+
+#[tokio::test]
+async fn clean_engine() {
+    let report = lint(&["play"]).await;
+    assert_eq!(report.violations(), 0, "{}", report);
+    assert!(report.scenarios.iter().all(|scenario| scenario.skipped.is_none()), "{}", report);
+}
+
+#[tokio::test]
+async fn missing_uciok() {
+    let script = ["expect uci", "send id name Mock", "send id author Cozy", "play"];
+    let report = lint_script(&script.map(str::to_owned)).await;
+    let handshake = scenario(&report, "handshake");
+    assert_eq!(handshake.violations, ["handshake: no response after 1.000s"], "{}", report);
+}
+
+#[tokio::test]
+async fn ignores_stop() {
+    let report = lint(&["expect go infinite", "expect stop", "sleep 500", "send bestmove e2e4"]).await;
+    let stop = scenario(&report, "stop");
+    assert_eq!(stop.violations.len(), 1, "{}", report);
+    assert!(stop.violations[0].starts_with("stop: bestmove took"), "{}", report);
+}
+
+#[tokio::test]
+async fn ignores_isready_during_search() {
+    let report = lint(&["expect go infinite", "ignore isready", "expect stop", "send bestmove e2e4"]).await;
+    let isready = scenario(&report, "isready during search");
+    assert_eq!(isready.violations, ["readyok: no response after 1.000s"], "{}", report);
+}